@@ -0,0 +1,278 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::raster::{self, Raster, Rgb};
+
+// One generation as text, one char per cell.
+pub type Frame = Vec<Vec<char>>;
+
+pub fn frame_from_str(s: &str) -> Frame {
+    s.lines().map(|l| l.chars().collect()).collect()
+}
+
+pub fn frame_to_string(frame: &Frame) -> String {
+    frame
+        .iter()
+        .map(|l| l.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Colours for the chars used by the day views.
+pub fn palette(c: char) -> Rgb {
+    match c {
+        '#' => [230, 200, 40],
+        'L' => [90, 110, 160],
+        '.' => [30, 30, 40],
+        _ => [0, 0, 0],
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Pause,
+    Step,
+    Back,
+    Faster,
+    Slower,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    // A lone space pauses, so it is matched before trimming.
+    fn from_str(s: &str) -> Result<Command, String> {
+        if s.trim_end_matches(&['\r', '\n'][..]) == " " {
+            return Ok(Command::Pause);
+        }
+        match s.trim() {
+            "p" => Ok(Command::Pause),
+            "" | "n" => Ok(Command::Step),
+            "b" => Ok(Command::Back),
+            "+" => Ok(Command::Faster),
+            "-" => Ok(Command::Slower),
+            "q" => Ok(Command::Quit),
+            s => Err(format!("Unknown command: {}", s)),
+        }
+    }
+}
+
+const MIN_DELAY: Duration = Duration::from_millis(10);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+// Position in the animation, stops on the last frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    frame: usize,
+    len: usize,
+    paused: bool,
+    delay: Duration,
+}
+
+impl Playback {
+    pub fn new(len: usize, delay: Duration) -> Playback {
+        Playback { frame: 0, len, paused: false, delay }
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    // Returns false once the viewer should quit.
+    pub fn apply(&mut self, cmd: Command) -> bool {
+        match cmd {
+            Command::Pause => self.paused = !self.paused,
+            Command::Step => {
+                self.paused = true;
+                self.frame = (self.frame + 1).min(self.len.saturating_sub(1));
+            }
+            Command::Back => {
+                self.paused = true;
+                self.frame = self.frame.saturating_sub(1);
+            }
+            Command::Faster => self.delay = (self.delay / 2).max(MIN_DELAY),
+            Command::Slower => self.delay = (self.delay * 2).min(MAX_DELAY),
+            Command::Quit => return false,
+        }
+        true
+    }
+
+    // Moves to the next frame unless paused, returns false at the end.
+    pub fn tick(&mut self) -> bool {
+        if self.frame + 1 >= self.len {
+            return false;
+        }
+        if !self.paused {
+            self.frame += 1;
+        }
+        true
+    }
+
+    pub fn status(&self) -> String {
+        format!(
+            "generation {}/{}  {}ms{}  [p]ause [n]ext [b]ack [+/-] speed [q]uit",
+            self.frame,
+            self.len.saturating_sub(1),
+            self.delay.as_millis(),
+            if self.paused { "  (paused)" } else { "" },
+        )
+    }
+}
+
+// Clears the screen and draws the frame with a status line below.
+pub fn render(frame: &Frame, status: &str) -> String {
+    format!("\x1b[H\x1b[2J{}\n\n{}\n", frame_to_string(frame), status)
+}
+
+// Commands are read a line at a time, so each one is followed by enter.
+pub fn play(frames: &[Frame], delay: Duration) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let cmd = match line {
+                Ok(l) => l.parse::<Command>().ok(),
+                Err(_) => Some(Command::Quit),
+            };
+            if let Some(cmd) = cmd {
+                if tx.send(cmd).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut playback = Playback::new(frames.len(), delay);
+    let stdout = io::stdout();
+    loop {
+        if let Some(frame) = frames.get(playback.frame()) {
+            let mut out = stdout.lock();
+            out.write_all(render(frame, &playback.status()).as_bytes())?;
+            out.flush()?;
+        }
+
+        let cmd = if playback.is_paused() {
+            rx.recv().ok()
+        } else {
+            match rx.recv_timeout(playback.delay()) {
+                Ok(cmd) => Some(cmd),
+                Err(RecvTimeoutError::Timeout) => {
+                    if !playback.tick() {
+                        break;
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => None,
+            }
+        };
+
+        match cmd {
+            Some(cmd) if playback.apply(cmd) => {}
+            Some(_) => break,
+            None if playback.is_paused() => break,
+            None => {
+                if !playback.tick() {
+                    break;
+                }
+                thread::sleep(playback.delay());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Frames of different sizes are padded with blanks to the largest one.
+pub fn rasterize(frames: &[Frame], scale: usize) -> Vec<Raster> {
+    let height = frames.iter().map(|f| f.len()).max().unwrap_or(0);
+    let width = frames
+        .iter()
+        .flat_map(|f| f.iter().map(|l| l.len()))
+        .max()
+        .unwrap_or(0);
+
+    frames
+        .iter()
+        .map(|f| {
+            let mut padded = vec![vec![' '; width]; height];
+            for (dst, src) in padded.iter_mut().zip(f.iter()) {
+                dst[..src.len()].copy_from_slice(src);
+            }
+            Raster::from_grid(&padded, scale, |&c| palette(c))
+        })
+        .collect()
+}
+
+// One frame_NNNN.ppm file per generation.
+pub fn write_ppm_frames(dir: &Path, frames: &[Frame], scale: usize) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (i, r) in rasterize(frames, scale).iter().enumerate() {
+        fs::write(dir.join(format!("frame_{:04}.ppm", i)), r.to_ppm())?;
+    }
+    Ok(())
+}
+
+pub fn write_gif(path: &Path, frames: &[Frame], scale: usize, delay: Duration) -> io::Result<()> {
+    let delay = (delay.as_millis() / 10).min(u16::MAX as u128) as u16;
+    let gif = raster::to_gif(&rasterize(frames, scale), delay)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No frames to encode"))?;
+    fs::write(path, gif)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playback() {
+        let mut p = Playback::new(3, Duration::from_millis(100));
+        assert!(p.tick());
+        assert_eq!(p.frame(), 1);
+
+        assert!(p.apply("p".parse().unwrap()));
+        assert!(p.tick());
+        assert_eq!(p.frame(), 1);
+        assert!(p.apply("b".parse().unwrap()));
+        assert_eq!(p.frame(), 0);
+        assert!(p.apply("".parse().unwrap()));
+        assert!(p.apply("n".parse().unwrap()));
+        assert!(p.apply("n".parse().unwrap()));
+        assert_eq!(p.frame(), 2);
+        assert!(!p.tick());
+
+        assert!(p.apply(Command::Faster));
+        assert_eq!(p.delay(), Duration::from_millis(50));
+        assert!(p.status().contains("2/2  50ms  (paused)"));
+        assert!(!p.apply(Command::Quit));
+        assert!("x".parse::<Command>().is_err());
+        assert_eq!(" ".parse::<Command>(), Ok(Command::Pause));
+        assert_eq!(" \n".parse::<Command>(), Ok(Command::Pause));
+        assert_eq!("  ".parse::<Command>(), Ok(Command::Step));
+    }
+
+    #[test]
+    fn test_rasterize() {
+        let frames = vec![frame_from_str("#"), frame_from_str("L.\n.#")];
+        assert_eq!(frame_to_string(&frames[1]), "L.\n.#");
+        assert_eq!(render(&frames[0], "s"), "\x1b[H\x1b[2J#\n\ns\n");
+
+        let r = rasterize(&frames, 2);
+        assert_eq!((r[0].width(), r[0].height()), (4, 4));
+        assert_eq!(r[0].get(1, 1), palette('#'));
+        assert_eq!(r[0].get(3, 3), palette(' '));
+        assert_eq!(r[1].get(0, 0), palette('L'));
+    }
+}
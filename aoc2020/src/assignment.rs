@@ -0,0 +1,278 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+// Every item gets a different value among its candidates: columns and fields
+// in day16, allergens and ingredients in day21.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssignmentError {
+    // The item can't get a value once the others have one.
+    Infeasible(usize),
+    // Two valid assignments give the item different values.
+    Ambiguous { item: usize, values: (usize, usize) },
+}
+
+impl fmt::Display for AssignmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssignmentError::Infeasible(i) => write!(f, "no value left for item {}", i),
+            AssignmentError::Ambiguous { item, values: (a, b) } =>
+                write!(f, "item {} can be either {} or {}", item, a, b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    domains: Vec<Vec<usize>>,
+    values: usize,
+}
+
+// Unit propagation: an item with a single candidate takes it away from the
+// others and, when there are as many values as items, a value that only one
+// item accepts goes to that item.
+fn reduce(domains: &mut [Vec<usize>], values: usize) -> Result<(), AssignmentError> {
+    let square = domains.len() == values;
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+        for i in 0..domains.len() {
+            match domains[i][..] {
+                [] => return Err(AssignmentError::Infeasible(i)),
+                [v] => {
+                    for (j, d) in domains.iter_mut().enumerate() {
+                        if j != i && d.contains(&v) {
+                            d.retain(|&w| w != v);
+                            changed = true;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if square && !changed {
+            let mut owners: Vec<Vec<usize>> = vec![Vec::new(); values];
+            for (i, d) in domains.iter().enumerate() {
+                for &v in d.iter() {
+                    owners[v].push(i);
+                }
+            }
+            for (v, o) in owners.iter().enumerate() {
+                if let [i] = o[..] {
+                    if domains[i].len() > 1 {
+                        domains[i] = vec![v];
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Hopcroft-Karp, the value matched to each item if any.
+fn max_matching(domains: &[Vec<usize>], values: usize) -> Vec<Option<usize>> {
+    const INF: usize = usize::MAX;
+
+    fn augment(
+        i: usize,
+        domains: &[Vec<usize>],
+        dist: &mut [usize],
+        item_of: &mut [Option<usize>],
+        value_of: &mut [Option<usize>],
+    ) -> bool {
+        for &v in domains[i].iter() {
+            let next = match item_of[v] {
+                None => true,
+                Some(j) => dist[j] == dist[i] + 1 && augment(j, domains, dist, item_of, value_of),
+            };
+            if next {
+                item_of[v] = Some(i);
+                value_of[i] = Some(v);
+                return true;
+            }
+        }
+        dist[i] = INF;
+        false
+    }
+
+    let mut value_of: Vec<Option<usize>> = vec![None; domains.len()];
+    let mut item_of: Vec<Option<usize>> = vec![None; values];
+    let mut dist = vec![INF; domains.len()];
+
+    loop {
+        let mut queue = VecDeque::new();
+        for (i, d) in dist.iter_mut().enumerate() {
+            *d = if value_of[i].is_none() { 0 } else { INF };
+            if *d == 0 {
+                queue.push_back(i);
+            }
+        }
+
+        let mut found = false;
+        while let Some(i) = queue.pop_front() {
+            for &v in domains[i].iter() {
+                match item_of[v] {
+                    None => found = true,
+                    Some(j) if dist[j] == INF => {
+                        dist[j] = dist[i] + 1;
+                        queue.push_back(j);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if !found {
+            break;
+        }
+
+        for i in 0..domains.len() {
+            if value_of[i].is_none() {
+                augment(i, domains, &mut dist, &mut item_of, &mut value_of);
+            }
+        }
+    }
+
+    value_of
+}
+
+impl Problem {
+    pub fn new(values: usize) -> Problem {
+        Problem { domains: Vec::new(), values }
+    }
+
+    // Returns the index of the new item.
+    pub fn add_item(&mut self, candidates: impl IntoIterator<Item = usize>) -> usize {
+        let mut domain: Vec<usize> = candidates.into_iter().filter(|&v| v < self.values).collect();
+        domain.sort_unstable();
+        domain.dedup();
+        self.domains.push(domain);
+        self.domains.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.domains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+
+    // What unit propagation alone decides.
+    pub fn propagate(&self) -> Result<Vec<Option<usize>>, AssignmentError> {
+        let mut domains = self.domains.clone();
+        reduce(&mut domains, self.values)?;
+        Ok(domains.iter().map(|d| if d.len() == 1 { Some(d[0]) } else { None }).collect())
+    }
+
+    pub fn max_matching(&self) -> Vec<Option<usize>> {
+        max_matching(&self.domains, self.values)
+    }
+
+    // Propagation, then backtracking on the item with the fewest candidates.
+    // Branches without a full matching are cut.
+    fn search(&self, domains: &mut [Vec<usize>], limit: usize, out: &mut Vec<Vec<usize>>) {
+        if out.len() >= limit || reduce(domains, self.values).is_err() {
+            return;
+        }
+        if max_matching(domains, self.values).iter().any(|v| v.is_none()) {
+            return;
+        }
+
+        let branch = (0..domains.len())
+            .filter(|&i| domains[i].len() > 1)
+            .min_by_key(|&i| domains[i].len());
+        match branch {
+            None => out.push(domains.iter().map(|d| d[0]).collect()),
+            Some(i) => {
+                for &v in domains[i].clone().iter() {
+                    let mut next = domains.to_vec();
+                    next[i] = vec![v];
+                    self.search(&mut next, limit, out);
+                }
+            }
+        }
+    }
+
+    pub fn solutions(&self) -> Vec<Vec<usize>> {
+        let mut out = Vec::new();
+        self.search(&mut self.domains.clone(), usize::MAX, &mut out);
+        out
+    }
+
+    // The only valid assignment, the value of each item.
+    pub fn solve(&self) -> Result<Vec<usize>, AssignmentError> {
+        let mut out = Vec::new();
+        self.search(&mut self.domains.clone(), 2, &mut out);
+
+        match &out[..] {
+            [] => {
+                let mut domains = self.domains.clone();
+                reduce(&mut domains, self.values)?;
+                let unmatched = max_matching(&domains, self.values).iter().position(|v| v.is_none());
+                Err(AssignmentError::Infeasible(unmatched.unwrap_or(0)))
+            }
+            [a] => Ok(a.clone()),
+            [a, b, ..] => {
+                let item = (0..a.len()).find(|&i| a[i] != b[i]).unwrap();
+                Err(AssignmentError::Ambiguous { item, values: (a[item], b[item]) })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem(values: usize, domains: &[&[usize]]) -> Problem {
+        let mut p = Problem::new(values);
+        for d in domains {
+            p.add_item(d.iter().copied());
+        }
+        p
+    }
+
+    #[test]
+    fn test_propagate() {
+        let p = problem(3, &[&[0, 1, 2], &[1], &[1, 2]]);
+        assert_eq!(p.propagate(), Ok(vec![Some(0), Some(1), Some(2)]));
+        assert_eq!(p.solve(), Ok(vec![0, 1, 2]));
+
+        // Only item 2 accepts value 0.
+        let p = problem(3, &[&[1, 2], &[1, 2], &[0, 1, 2]]);
+        assert_eq!(p.propagate(), Ok(vec![None, None, Some(0)]));
+
+        // More values than items, no hidden singles.
+        let p = problem(4, &[&[0, 3], &[0]]);
+        assert_eq!(p.propagate(), Ok(vec![Some(3), Some(0)]));
+        assert_eq!(problem(2, &[&[0], &[0]]).propagate(), Err(AssignmentError::Infeasible(1)));
+    }
+
+    #[test]
+    fn test_matching() {
+        let p = problem(3, &[&[0, 1], &[0], &[0, 2]]);
+        let m = p.max_matching();
+        assert_eq!(m, vec![Some(1), Some(0), Some(2)]);
+        assert_eq!(problem(3, &[&[0], &[0], &[1, 2]]).max_matching().iter().flatten().count(), 2);
+    }
+
+    #[test]
+    fn test_solve() {
+        // Propagation gets stuck, only the search finds the assignment.
+        let p = problem(4, &[&[0, 1], &[0, 1, 2], &[1, 2, 3], &[2, 3]]);
+        assert_eq!(p.propagate(), Ok(vec![None; 4]));
+        assert_eq!(p.solutions().len(), 5);
+        assert!(matches!(p.solve(), Err(AssignmentError::Ambiguous { .. })));
+
+        let p = problem(3, &[&[0, 1], &[0, 1], &[0, 1, 2]]);
+        assert_eq!(p.solutions(), vec![vec![0, 1, 2], vec![1, 0, 2]]);
+        assert_eq!(p.solve(), Err(AssignmentError::Ambiguous { item: 0, values: (0, 1) }));
+
+        let p = problem(3, &[&[0, 1], &[0, 1], &[0, 1]]);
+        assert_eq!(p.solutions(), Vec::<Vec<usize>>::new());
+        assert_eq!(p.solve(), Err(AssignmentError::Infeasible(2)));
+    }
+}
@@ -0,0 +1,388 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+// Little-endian base 2^32 magnitude, never has trailing zero limbs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    neg: bool,
+    mag: Vec<u32>,
+}
+
+fn trim(mut v: Vec<u32>) -> Vec<u32> {
+    while v.last() == Some(&0) {
+        v.pop();
+    }
+    v
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut ret = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u64;
+
+    for (i, &x) in a.iter().enumerate() {
+        let sum = x as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        ret.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry != 0 {
+        ret.push(carry as u32);
+    }
+
+    ret
+}
+
+// Requires a >= b.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut ret = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+
+    for (i, &x) in a.iter().enumerate() {
+        let mut diff = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = if diff < 0 { 1 } else { 0 };
+        if diff < 0 {
+            diff += 1 << 32;
+        }
+        ret.push(diff as u32);
+    }
+
+    trim(ret)
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+
+    let mut ret = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let cur = ret[i + j] as u64 + x as u64 * y as u64 + carry;
+            ret[i + j] = cur as u32;
+            carry = cur >> 32;
+        }
+        ret[i + b.len()] = carry as u32;
+    }
+
+    trim(ret)
+}
+
+fn divmod_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0u32; a.len()];
+    let mut rem = 0u64;
+
+    for i in (0..a.len()).rev() {
+        let cur = (rem << 32) | a[i] as u64;
+        q[i] = (cur / d as u64) as u32;
+        rem = cur % d as u64;
+    }
+
+    (trim(q), rem as u32)
+}
+
+fn shl1(a: &mut Vec<u32>, bit: u32) {
+    let mut carry = bit;
+    for x in a.iter_mut() {
+        let next = *x >> 31;
+        *x = (*x << 1) | carry;
+        carry = next;
+    }
+    if carry != 0 {
+        a.push(carry);
+    }
+}
+
+// Plain shift-and-subtract long division, good enough for puzzle-sized numbers.
+fn divmod_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    assert!(!b.is_empty(), "Division by zero");
+
+    if cmp_mag(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let (q, r) = divmod_small(a, b[0]);
+        return (q, trim(vec![r]));
+    }
+
+    let mut q = vec![0u32; a.len()];
+    let mut r: Vec<u32> = Vec::new();
+
+    for i in (0..a.len() * 32).rev() {
+        shl1(&mut r, (a[i / 32] >> (i % 32)) & 1);
+        if cmp_mag(&r, b) != Ordering::Less {
+            r = sub_mag(&r, b);
+            q[i / 32] |= 1 << (i % 32);
+        }
+    }
+
+    (trim(q), r)
+}
+
+impl BigInt {
+    fn from_parts(neg: bool, mag: Vec<u32>) -> BigInt {
+        let mag = trim(mag);
+        BigInt { neg: neg && !mag.is_empty(), mag }
+    }
+
+    pub fn zero() -> BigInt {
+        BigInt::default()
+    }
+
+    pub fn one() -> BigInt {
+        BigInt::from(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.neg
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt::from_parts(false, self.mag.clone())
+    }
+
+    // Truncating division, the remainder has the sign of the dividend.
+    pub fn div_rem(&self, other: &BigInt) -> (BigInt, BigInt) {
+        let (q, r) = divmod_mag(&self.mag, &other.mag);
+        (
+            BigInt::from_parts(self.neg != other.neg, q),
+            BigInt::from_parts(self.neg, r),
+        )
+    }
+
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let mut a = self.abs();
+        let mut b = other.abs();
+
+        while !b.is_zero() {
+            let (_, r) = a.div_rem(&b);
+            a = b;
+            b = r;
+        }
+
+        a
+    }
+}
+
+impl From<i128> for BigInt {
+    fn from(n: i128) -> BigInt {
+        let mut mag = Vec::new();
+        let mut m = n.unsigned_abs();
+
+        while m != 0 {
+            mag.push(m as u32);
+            m >>= 32;
+        }
+
+        BigInt::from_parts(n < 0, mag)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.neg, other.neg) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.neg, self.mag)
+    }
+}
+
+impl Add for BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: BigInt) -> BigInt {
+        if self.neg == other.neg {
+            return BigInt::from_parts(self.neg, add_mag(&self.mag, &other.mag));
+        }
+
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::from_parts(other.neg, sub_mag(&other.mag, &self.mag)),
+            _ => BigInt::from_parts(self.neg, sub_mag(&self.mag, &other.mag)),
+        }
+    }
+}
+
+impl Sub for BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: BigInt) -> BigInt {
+        self + -other
+    }
+}
+
+impl Mul for BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: BigInt) -> BigInt {
+        BigInt::from_parts(self.neg != other.neg, mul_mag(&self.mag, &other.mag))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        let mut chunks = Vec::new();
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            let (q, r) = divmod_small(&mag, 1_000_000_000);
+            chunks.push(r);
+            mag = q;
+        }
+
+        if self.neg {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for c in chunks.iter().rev() {
+            write!(f, "{:09}", c)?;
+        }
+        Ok(())
+    }
+}
+
+// Always kept reduced with a positive denominator.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: BigInt,
+    den: BigInt,
+}
+
+impl Rational {
+    pub fn new(num: BigInt, den: BigInt) -> Option<Rational> {
+        if den.is_zero() {
+            return None;
+        }
+
+        let g = num.gcd(&den);
+        let (num, _) = num.div_rem(&g);
+        let (den, _) = den.div_rem(&g);
+        Some(if den.is_negative() {
+            Rational { num: -num, den: -den }
+        } else {
+            Rational { num, den }
+        })
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        &self.num
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        &self.den
+    }
+
+    pub fn checked_div(self, other: Rational) -> Option<Rational> {
+        Rational::new(self.num * other.den, self.den * other.num)
+    }
+}
+
+impl From<BigInt> for Rational {
+    fn from(n: BigInt) -> Rational {
+        Rational { num: n, den: BigInt::one() }
+    }
+}
+
+impl From<i128> for Rational {
+    fn from(n: i128) -> Rational {
+        Rational::from(BigInt::from(n))
+    }
+}
+
+impl Add for Rational {
+    type Output = Rational;
+
+    fn add(self, other: Rational) -> Rational {
+        let num = self.num * other.den.clone() + other.num * self.den.clone();
+        Rational::new(num, self.den * other.den).unwrap()
+    }
+}
+
+impl Sub for Rational {
+    type Output = Rational;
+
+    fn sub(self, other: Rational) -> Rational {
+        self + Rational { num: -other.num, den: other.den }
+    }
+}
+
+impl Mul for Rational {
+    type Output = Rational;
+
+    fn mul(self, other: Rational) -> Rational {
+        Rational::new(self.num * other.num, self.den * other.den).unwrap()
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == BigInt::one() {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bigint() {
+        let a = BigInt::from(i64::MAX as i128);
+        let b = a.clone() * a.clone() * a.clone();
+        assert_eq!(b.to_string(), "784637716923335095224261902710254454442933591094742482943");
+
+        let (q, r) = b.div_rem(&a);
+        assert_eq!(q, a.clone() * a.clone());
+        assert!(r.is_zero());
+
+        assert_eq!((BigInt::from(5) - BigInt::from(12)).to_string(), "-7");
+        assert_eq!(BigInt::from(-3) * BigInt::from(-4), BigInt::from(12));
+        assert_eq!(BigInt::from(-1_000_000_000_000), -BigInt::from(1_000_000_000_000));
+        assert!(BigInt::from(-2) < BigInt::from(1));
+        assert_eq!(BigInt::from(84).gcd(&BigInt::from(-36)), BigInt::from(12));
+    }
+
+    #[test]
+    fn test_rational() {
+        let half = Rational::new(BigInt::from(2), BigInt::from(-4)).unwrap();
+        assert_eq!(half.to_string(), "-1/2");
+
+        let third = Rational::new(BigInt::from(1), BigInt::from(3)).unwrap();
+        assert_eq!((half.clone() + third.clone()).to_string(), "-1/6");
+        assert_eq!((half.clone() * third.clone()).to_string(), "-1/6");
+        assert_eq!(half.clone().checked_div(third).unwrap().to_string(), "-3/2");
+        assert_eq!(half.checked_div(Rational::from(0)), None);
+    }
+}
@@ -0,0 +1,123 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+use aoc2020::animation::{self, Frame};
+use aoc2020::cellular::Rule;
+use aoc2020::{day11, day17, day24};
+
+const USAGE: &str = "usage: view <day11|day17|day24> <input> [--part 1|2] [--dims N] [--rule B3/S23]
+            [--cycles N] [--delay MS] [--scale N] [--gif FILE] [--ppm DIR]";
+
+struct Options {
+    day: String,
+    input: String,
+    part: usize,
+    dims: usize,
+    rule: String,
+    cycles: Option<usize>,
+    delay: Duration,
+    scale: usize,
+    gif: Option<String>,
+    ppm: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut positional = Vec::new();
+    let mut opts = Options {
+        day: String::new(),
+        input: String::new(),
+        part: 1,
+        dims: 3,
+        rule: "B3/S23".to_string(),
+        cycles: None,
+        delay: Duration::from_millis(200),
+        scale: 4,
+        gif: None,
+        ppm: None,
+    };
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg.clone());
+            continue;
+        }
+
+        let value = it.next().ok_or(format!("Missing value for {}", arg))?;
+        let number = || value.parse::<usize>().map_err(|_| format!("Invalid value for {}: {}", arg, value));
+        match arg.as_str() {
+            "--part" => opts.part = number()?,
+            "--dims" => opts.dims = number()?,
+            "--rule" => opts.rule = value.clone(),
+            "--cycles" => opts.cycles = Some(number()?),
+            "--delay" => opts.delay = Duration::from_millis(number()? as u64),
+            "--scale" => opts.scale = number()?,
+            "--gif" => opts.gif = Some(value.clone()),
+            "--ppm" => opts.ppm = Some(value.clone()),
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+
+    match positional.as_slice() {
+        [day, input] => {
+            opts.day = day.clone();
+            opts.input = input.clone();
+            Ok(opts)
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn frames(opts: &Options) -> Result<Vec<Frame>, String> {
+    let input = fs::read_to_string(&opts.input).map_err(|e| format!("{}: {}", opts.input, e))?;
+    let input = input.trim_end();
+
+    match opts.day.as_str() {
+        "day11" => {
+            let depth = if opts.part == 2 { day11::Depth::Inf } else { day11::Depth::Next };
+            Ok(day11::frames(&day11::gen(input), depth))
+        }
+        "day17" => {
+            let rule: Rule = opts.rule.parse().map_err(|_| format!("Invalid rule: {}", opts.rule))?;
+            let cells = day17::gen(input);
+            let cycles = opts.cycles.unwrap_or(6);
+            match opts.dims {
+                2 => Ok(day17::frames::<2>(&cells, rule, cycles)),
+                3 => Ok(day17::frames::<3>(&cells, rule, cycles)),
+                4 => Ok(day17::frames::<4>(&cells, rule, cycles)),
+                5 => Ok(day17::frames::<5>(&cells, rule, cycles)),
+                6 => Ok(day17::frames::<6>(&cells, rule, cycles)),
+                n => Err(format!("Unsupported number of dimensions: {}", n)),
+            }
+        }
+        "day24" => Ok(day24::frames(&day24::gen(input), opts.cycles.unwrap_or(100))),
+        day => Err(format!("No viewer for {}", day)),
+    }
+}
+
+fn run(opts: &Options) -> Result<(), String> {
+    let frames = frames(opts)?;
+
+    if opts.gif.is_none() && opts.ppm.is_none() {
+        return animation::play(&frames, opts.delay).map_err(|e| e.to_string());
+    }
+    if let Some(path) = &opts.gif {
+        animation::write_gif(Path::new(path), &frames, opts.scale, opts.delay).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(dir) = &opts.ppm {
+        animation::write_ppm_frames(Path::new(dir), &frames, opts.scale).map_err(|e| format!("{}: {}", dir, e))?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if let Err(e) = parse_args(&args).and_then(|opts| run(&opts)) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,329 @@
+use std::mem;
+use std::str::FromStr;
+
+const DIRS: [(isize, isize); 8] = [
+    (-1, -1), (0, -1), (1, -1),
+    (-1, 0), (1, 0),
+    (-1, 1), (0, 1), (1, 1),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid<C> {
+    width: usize,
+    height: usize,
+    cells: Vec<C>,
+}
+
+impl<C> Grid<C> {
+    pub fn new(rows: Vec<Vec<C>>) -> Grid<C> {
+        let height = rows.len();
+        let width = rows.first().map_or(0, |r| r.len());
+        assert!(rows.iter().all(|r| r.len() == width), "Grid rows must have the same length");
+
+        Grid {
+            width,
+            height,
+            cells: rows.into_iter().flatten().collect(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: isize, y: isize) -> Option<&C> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            None
+        } else {
+            Some(&self.cells[y as usize * self.width + x as usize])
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[C]> {
+        self.cells.chunks(self.width.max(1)).take(self.height)
+    }
+
+    pub fn cells(&self) -> &[C] {
+        &self.cells
+    }
+
+    pub fn count(&self, f: impl Fn(&C) -> bool) -> usize {
+        self.cells.iter().filter(|c| f(c)).count()
+    }
+}
+
+// The eight adjacent cells inside the grid.
+pub fn moore<C>(grid: &Grid<C>, x: usize, y: usize) -> Vec<(usize, usize)> {
+    DIRS
+        .iter()
+        .map(|(dx, dy)| (x as isize + dx, y as isize + dy))
+        .filter(|&(x, y)| grid.get(x, y).is_some())
+        .map(|(x, y)| (x as usize, y as usize))
+        .collect()
+}
+
+// First cell in each of the eight directions that `see_through` can't see
+// through.
+pub fn line_of_sight<C>(see_through: impl Fn(&C) -> bool) -> impl Fn(&Grid<C>, usize, usize) -> Vec<(usize, usize)> {
+    move |grid, x, y| DIRS
+        .iter()
+        .filter_map(|(dx, dy)| {
+            let (mut x, mut y) = (x as isize + dx, y as isize + dy);
+            while let Some(c) = grid.get(x, y) {
+                if !see_through(c) {
+                    return Some((x as usize, y as usize));
+                }
+                x += dx;
+                y += dy;
+            }
+            None
+        })
+        .collect()
+}
+
+// Neighbour lists are computed once from the starting grid, so the
+// neighbourhood may only depend on cells that never change.
+pub struct CellularAutomaton<C, R> {
+    grid: Grid<C>,
+    next: Vec<C>,
+    starts: Vec<usize>,
+    adjacency: Vec<usize>,
+    scratch: Vec<C>,
+    rule: R,
+    generation: usize,
+}
+
+impl<C: Clone + PartialEq, R: Fn(&C, &[C]) -> C> CellularAutomaton<C, R> {
+    pub fn new(grid: Grid<C>, neighbourhood: impl Fn(&Grid<C>, usize, usize) -> Vec<(usize, usize)>, rule: R) -> Self {
+        let mut starts = vec![0];
+        let mut adjacency = Vec::new();
+
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                adjacency.extend(neighbourhood(&grid, x, y).into_iter().map(|(x, y)| y * grid.width + x));
+                starts.push(adjacency.len());
+            }
+        }
+
+        CellularAutomaton {
+            next: grid.cells.clone(),
+            grid,
+            starts,
+            adjacency,
+            scratch: Vec::with_capacity(8),
+            rule,
+            generation: 0,
+        }
+    }
+
+    pub fn grid(&self) -> &Grid<C> {
+        &self.grid
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    // Returns the number of cells that changed.
+    pub fn step(&mut self) -> usize {
+        let mut changes = 0;
+        let cells = &self.grid.cells;
+
+        for (i, next) in self.next.iter_mut().enumerate() {
+            self.scratch.clear();
+            self.scratch.extend(
+                self.adjacency[self.starts[i]..self.starts[i + 1]]
+                    .iter()
+                    .map(|&j| cells[j].clone())
+            );
+
+            let cell = (self.rule)(&cells[i], &self.scratch);
+            if cell != cells[i] {
+                changes += 1;
+            }
+            *next = cell;
+        }
+
+        mem::swap(&mut self.grid.cells, &mut self.next);
+        self.generation += 1;
+        changes
+    }
+
+    // The generation after `grid`, which has the shape of the starting grid,
+    // without touching the automaton's own grid.
+    pub fn apply(&self, grid: &Grid<C>) -> Grid<C> {
+        assert_eq!((grid.width, grid.height), (self.grid.width, self.grid.height), "Grid of another automaton");
+        let mut scratch = Vec::with_capacity(8);
+        let cells = grid.cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                scratch.clear();
+                scratch.extend(
+                    self.adjacency[self.starts[i]..self.starts[i + 1]]
+                        .iter()
+                        .map(|&j| grid.cells[j].clone())
+                );
+                (self.rule)(cell, &scratch)
+            })
+            .collect();
+
+        Grid { width: grid.width, height: grid.height, cells }
+    }
+
+    // Steps until nothing changes anymore, or `limit` steps were made.
+    // Returns true when a fixed point was reached.
+    pub fn run_until_stable(&mut self, limit: Option<usize>) -> bool {
+        let mut steps = 0;
+
+        while limit != Some(steps) {
+            steps += 1;
+            if self.step() == 0 {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+// Birth/survival neighbour counts written as "B3/S23", one digit per count.
+// For larger neighbourhoods, a rule with a comma or a range anywhere takes
+// whole numbers instead: "B12/S2-4,10".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    birth: Vec<usize>,
+    survive: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RuleError;
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn counts(s: &str, prefix: char, list: bool) -> Result<Vec<usize>, RuleError> {
+            let s = s.strip_prefix(prefix).ok_or(RuleError)?;
+            if !list {
+                return s.chars()
+                    .map(|c| c.to_digit(10).map(|n| n as usize).ok_or(RuleError))
+                    .collect();
+            }
+
+            let mut ret = Vec::new();
+            for item in s.split(',').filter(|_| !s.is_empty()) {
+                let mut bounds = item.splitn(2, '-').map(|n| n.trim().parse::<usize>().map_err(|_| RuleError));
+                let lower = bounds.next().unwrap()?;
+                let upper = bounds.next().unwrap_or(Ok(lower))?;
+                if upper < lower {
+                    return Err(RuleError);
+                }
+                ret.extend(lower..=upper);
+            }
+            Ok(ret)
+        }
+
+        let list = s.contains(&[',', '-'][..]);
+        let mut parts = s.trim().split('/');
+        let birth = counts(parts.next().ok_or(RuleError)?, 'B', list)?;
+        let survive = counts(parts.next().ok_or(RuleError)?, 'S', list)?;
+        if parts.next().is_some() {
+            return Err(RuleError);
+        }
+
+        Ok(Rule { birth, survive })
+    }
+}
+
+impl Rule {
+    pub fn new(birth: &[usize], survive: &[usize]) -> Rule {
+        Rule { birth: birth.to_vec(), survive: survive.to_vec() }
+    }
+
+    pub fn conway() -> Rule {
+        Rule { birth: vec![3], survive: vec![2, 3] }
+    }
+
+    pub fn next(&self, active: bool, neighbours: usize) -> bool {
+        if active {
+            self.survive.contains(&neighbours)
+        } else {
+            self.birth.contains(&neighbours)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{Cycle, Simulation};
+
+    fn life(c: &bool, n: &[bool]) -> bool {
+        let n = n.iter().filter(|&&b| b).count();
+        n == 3 || (*c && n == 2)
+    }
+
+    fn parse(s: &str) -> Grid<bool> {
+        Grid::new(s.lines().map(|l| l.chars().map(|c| c == '#').collect()).collect())
+    }
+
+    #[test]
+    fn test_neighbourhoods() {
+        let grid = Grid::new(vec![
+            "#..#".chars().collect(),
+            "....".chars().collect(),
+            "#.L.".chars().collect(),
+        ]);
+
+        assert_eq!(moore(&grid, 0, 0), vec![(1, 0), (0, 1), (1, 1)]);
+        assert_eq!(moore(&grid, 1, 1).len(), 8);
+
+        let sight = line_of_sight(|&c| c == '.');
+        assert_eq!(sight(&grid, 0, 0), vec![(3, 0), (0, 2), (2, 2)]);
+        assert_eq!(sight(&grid, 3, 2), vec![(3, 0), (2, 2)]);
+    }
+
+    #[test]
+    fn test_automaton() {
+        let mut blinker = CellularAutomaton::new(parse(".....\n..#..\n..#..\n..#..\n....."), moore, life);
+        let start = blinker.grid().clone();
+        assert_eq!(blinker.step(), 4);
+        assert_eq!(blinker.grid().rows().nth(2).unwrap(), &[false, true, true, true, false]);
+        blinker.step();
+        assert_eq!(blinker.grid(), &start);
+
+        let mut sim = Simulation::new(start.clone(), |g| blinker.apply(g));
+        assert_eq!(sim.find_cycle(None), Some(Cycle { start: 0, period: 2 }));
+        assert_eq!(sim.state(), &start);
+        assert!(!blinker.run_until_stable(Some(10)));
+        assert_eq!(blinker.generation(), 12);
+
+        let mut block = CellularAutomaton::new(parse("....\n.##.\n.##.\n...."), moore, life);
+        assert!(block.run_until_stable(None));
+        assert_eq!(block.generation(), 1);
+        assert_eq!(block.grid().count(|&b| b), 4);
+    }
+
+    #[test]
+    fn test_rule() {
+        assert_eq!("B3/S23".parse(), Ok(Rule::conway()));
+        assert_eq!("B36/S23".parse(), Ok(Rule::new(&[3, 6], &[2, 3])));
+        assert_eq!("B/S".parse(), Ok(Rule::new(&[], &[])));
+        assert_eq!("S23/B3".parse::<Rule>(), Err(RuleError));
+        assert_eq!("B3".parse::<Rule>(), Err(RuleError));
+        assert_eq!("B3/S2x".parse::<Rule>(), Err(RuleError));
+
+        assert_eq!("B3,12/S2,3".parse(), Ok(Rule::new(&[3, 12], &[2, 3])));
+        assert_eq!("B12,/S10-12".parse::<Rule>(), Err(RuleError));
+        assert_eq!("B/S2,3".parse(), Ok(Rule::new(&[], &[2, 3])));
+        assert_eq!("B12/S10-12".parse(), Ok(Rule::new(&[12], &[10, 11, 12])));
+        assert_eq!("B12/S10".parse(), Ok(Rule::new(&[1, 2], &[1, 0])));
+        assert_eq!("B3,26/S2-4,80".parse::<Rule>().map(|r| r.next(true, 80) && r.next(false, 26)), Ok(true));
+        assert_eq!("B3/S4-2".parse::<Rule>(), Err(RuleError));
+    }
+}
@@ -1,45 +1,29 @@
-use nom::lib::std::fmt::Formatter;
-use core::fmt;
+use crate::animation::Frame;
+use crate::cellular::{CellularAutomaton, Grid, line_of_sight, moore};
+use crate::simulation::Simulation;
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Seat {
     Floor,
     Empty,
     Occupied,
 }
 
-type Layout = Vec<Vec<Seat>>;
-
-pub struct State {
-    seats: Layout,
-    height: usize,
-    width: usize,
-    changes: usize,
-}
-
-#[derive(Copy, Clone)]
-pub struct Position {
-    x: isize,
-    y: isize,
-}
-
 #[derive(Copy, Clone, PartialEq)]
 pub enum Depth {
     Inf,
     Next,
 }
 
-impl fmt::Debug for Seat {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match *self {
-            Seat::Floor => write!(f, "."),
-            Seat::Empty => write!(f, "L"),
-            Seat::Occupied => write!(f, "#"),
+impl Seat {
+    fn to_char(self) -> char {
+        match self {
+            Seat::Floor => '.',
+            Seat::Empty => 'L',
+            Seat::Occupied => '#',
         }
     }
-}
 
-impl Seat {
     fn from(c: char) -> Seat {
         match c {
             '.' => Seat::Floor,
@@ -50,87 +34,16 @@ impl Seat {
     }
 }
 
-impl State {
-    fn new(seats: Layout) -> State {
-        let height = seats.len();
-        let width = seats[0].len();
-
-        State {
-            seats,
-            height,
-            width,
-            changes: 0,
-        }
-    }
-
-    fn next_seat(&self, pos: Position, depth: Depth) -> Seat {
-        let width = self.width as isize;
-        let height = self.height as isize;
-        let in_bounds = |x, y| x >= 0 && x < width && y >= 0 && y < height;
-        let dirs = [
-            (-1, -1), (-1, 0), (-1, 1),
-            (0, -1), (0, 1),
-            (1, -1), (1, 0), (1, 1)
-        ];
-
-        let count = dirs
-            .iter()
-            .fold(0, |acc, (xdir, ydir)| {
-                let mut p = Position { x: pos.x + xdir, y: pos.y + ydir };
-                while depth == Depth::Inf && in_bounds(p.x, p.y) &&
-                    self.seats[p.y as usize][p.x as usize] == Seat::Floor {
-                    p = Position { x: p.x + xdir, y: p.y + ydir };
-                }
-
-                if in_bounds(p.x, p.y) &&
-                    self.seats[p.y as usize][p.x as usize] == Seat::Occupied {
-                    acc + 1
-                } else {
-                    acc
-                }
-            });
-
-        let cond = match depth {
-            Depth::Inf => 5,
-            Depth::Next => 4
-        };
-        match &self.seats[pos.y as usize][pos.x as usize] {
-            Seat::Empty if count == 0 => Seat::Occupied,
-            Seat::Occupied if count >= cond => Seat::Empty,
-            seat => seat.clone()
-        }
-    }
-
-    fn update(&self, depth: Depth) -> State {
-        let mut seats: Layout = self.seats.to_vec();
-        let changes =
-            (0..self.height).fold(0, |acc, y|
-                (0..self.width).fold(0, |acc, x| {
-                    let p = Position { x: x as isize, y: y as isize };
-                    let seat = self.next_seat(p, depth);
-                    let change = if seat != seats[y][x] { 1 } else { 0 };
-                    seats[y][x] = seat;
-                    acc + change
-                }) + acc,
-            );
-
-        State {
-            seats,
-            changes,
-            ..*self
-        }
-    }
-
-    fn count_occupied(&self) -> usize {
-        self.seats.iter().fold(0, |acc, x|
-            acc + x.iter().fold(0, |acc, y|
-                acc + if *y == Seat::Occupied { 1 } else { 0 }))
-    }
+pub fn render(seats: &Grid<Seat>) -> Frame {
+    seats
+        .rows()
+        .map(|row| row.iter().map(|s| s.to_char()).collect())
+        .collect()
 }
 
 #[aoc_generator(day11)]
-pub fn gen(input: &str) -> State {
-    let seats: Layout = input
+pub fn gen(input: &str) -> Grid<Seat> {
+    let seats = input
         .lines()
         .map(|line| line
             .trim()
@@ -140,31 +53,61 @@ pub fn gen(input: &str) -> State {
         )
         .collect();
 
-    State::new(seats)
+    Grid::new(seats)
 }
 
-fn rec(state: &State, depth: Depth) -> usize {
-    let state = state.update(depth);
-    if state.changes == 0 {
-        state.count_occupied()
-    } else {
-        rec(&state, depth)
+fn automaton(seats: &Grid<Seat>, depth: Depth) -> CellularAutomaton<Seat, impl Fn(&Seat, &[Seat]) -> Seat> {
+    let cond = match depth {
+        Depth::Inf => 5,
+        Depth::Next => 4
+    };
+    let rule = move |seat: &Seat, neighbours: &[Seat]| {
+        let count = neighbours.iter().filter(|&s| *s == Seat::Occupied).count();
+        match seat {
+            Seat::Empty if count == 0 => Seat::Occupied,
+            Seat::Occupied if count >= cond => Seat::Empty,
+            seat => *seat
+        }
+    };
+
+    match depth {
+        Depth::Next => CellularAutomaton::new(seats.clone(), moore, rule),
+        Depth::Inf => CellularAutomaton::new(seats.clone(), line_of_sight(|s| *s == Seat::Floor), rule),
     }
 }
 
+fn simulate(seats: &Grid<Seat>, depth: Depth) -> usize {
+    let automaton = automaton(seats, depth);
+    let mut sim = Simulation::new(seats.clone(), |s| automaton.apply(s));
+    let cycle = sim.find_cycle(None).unwrap();
+    assert!(cycle.is_fixed_point(), "Seats keep changing every {} rounds", cycle.period);
+    sim.state().count(|s| *s == Seat::Occupied)
+}
+
+// Every generation up to the stable one.
+pub fn frames(seats: &Grid<Seat>, depth: Depth) -> Vec<Frame> {
+    let mut automaton = automaton(seats, depth);
+    let mut frames = vec![render(automaton.grid())];
+    while automaton.step() != 0 {
+        frames.push(render(automaton.grid()));
+    }
+    frames
+}
+
 #[aoc(day11, part1)]
-pub fn solve_part1(state: &State) -> usize {
-    rec(state, Depth::Next)
+pub fn solve_part1(seats: &Grid<Seat>) -> usize {
+    simulate(seats, Depth::Next)
 }
 
 #[aoc(day11, part2)]
-pub fn solve_part2(state: &State) -> usize {
-    rec(state, Depth::Inf)
+pub fn solve_part2(seats: &Grid<Seat>) -> usize {
+    simulate(seats, Depth::Inf)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::animation::frame_to_string;
 
     fn get_input() -> &'static str {
         return "L.LL.LL.LL
@@ -182,9 +125,15 @@ L.LLLLL.LL";
     #[test]
     fn test_gen() {
         let s = gen(get_input());
-        for v in s.seats {
-            println!("{:?}", v);
-        }
+        assert_eq!(frame_to_string(&render(&s)), get_input());
+    }
+
+    #[test]
+    fn test_frames() {
+        let frames = frames(&gen(get_input()), Depth::Next);
+        assert_eq!(frames.len(), 6);
+        assert_eq!(frame_to_string(&frames[1]).lines().next(), Some("#.##.##.##"));
+        assert_eq!(frames[5].iter().flatten().filter(|&&c| c == '#').count(), 37);
     }
 
     #[test]
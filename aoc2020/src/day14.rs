@@ -5,7 +5,6 @@ use nom::multi::many1;
 use nom::IResult;
 use nom::combinator::map;
 use nom::lib::std::collections::HashMap;
-use itertools::Itertools;
 use std::ops::Not;
 
 #[derive(Debug, Copy, Clone)]
@@ -140,47 +139,102 @@ pub fn solve_part1(states: &[State]) -> u64 {
     h.values().sum()
 }
 
-fn do_it_n_times(masks: &[Mask], value: u64) -> Vec<u64> {
-    if masks.is_empty() {
-        vec![value]
-    } else {
-        let mask = masks[0];
-        let value = value.unset_bit(mask.bit);
-        let mut v = do_it_n_times(&masks[1..], value);
-        let value = value.set_bit(mask.bit);
-        let mut w = do_it_n_times(&masks[1..], value);
+// Every address that matches `fixed` outside of the `floating` bits. The
+// floating bits of `fixed` are always 0.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    fixed: u64,
+    floating: u64,
+}
+
+impl Pattern {
+    // Memory address decoder of version 2: 1 sets the bit, X floats.
+    pub fn decode(masks: &[Mask], address: u64) -> Pattern {
+        masks.iter().fold(Pattern { fixed: address, floating: 0 }, |p, mask| {
+            if mask.x {
+                Pattern { fixed: p.fixed.unset_bit(mask.bit), floating: p.floating.set_bit(mask.bit) }
+            } else if mask.value == 1 {
+                Pattern { fixed: p.fixed.set_bit(mask.bit), ..p }
+            } else {
+                p
+            }
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        1 << self.floating.count_ones()
+    }
 
-        v.append(&mut w);
-        v
+    pub fn contains(&self, address: u64) -> bool {
+        address & !self.floating == self.fixed
+    }
+
+    fn intersects(&self, other: &Pattern) -> bool {
+        (self.fixed ^ other.fixed) & !(self.floating | other.floating) == 0
+    }
+
+    // Disjoint patterns covering the addresses of self that aren't in other:
+    // each bit that floats here but not there is fixed to the other value in
+    // turn.
+    fn subtract(&self, other: &Pattern) -> Vec<Pattern> {
+        if !self.intersects(other) {
+            return vec![*self];
+        }
+
+        let mut pieces = Vec::new();
+        let mut rest = *self;
+        let split = self.floating & !other.floating;
+        for bit in (0..64).filter(|b| split & (1 << b) != 0) {
+            let floating = rest.floating.unset_bit(bit);
+            let own = other.fixed & (1 << bit);
+            pieces.push(Pattern { fixed: rest.fixed | (own ^ (1 << bit)), floating });
+            rest = Pattern { fixed: rest.fixed | own, floating };
+        }
+        pieces
     }
 }
 
-#[aoc(day14, part2)]
-pub fn solve_part2(states: &[State]) -> u64 {
-    pub fn apply_masks(masks: &[Mask], val: &u64) -> u64 {
-        masks
+// Written patterns don't overlap: a write takes its addresses away from the
+// older ones. Cells holding 0 add nothing to the sum, so they are not kept.
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    cells: Vec<(Pattern, u64)>,
+}
+
+impl Memory {
+    pub fn write(&mut self, pattern: Pattern, value: u64) {
+        self.cells = self.cells
             .iter()
-            .fold(*val, |acc, mask|
-                if mask.x.not() && mask.value == 1 {
-                    acc.set_bit(mask.bit)
-                } else {
-                    acc
-                },
-            )
+            .flat_map(|&(p, v)| p.subtract(&pattern).into_iter().map(move |p| (p, v)))
+            .collect();
+        if value != 0 {
+            self.cells.push((pattern, value));
+        }
     }
-    let mut h: HashMap<u64, u64> = HashMap::new();
+
+    pub fn get(&self, address: u64) -> u64 {
+        self.cells
+            .iter()
+            .find(|(p, _)| p.contains(address))
+            .map_or(0, |&(_, v)| v)
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.cells.iter().map(|(p, v)| p.size() * v).sum()
+    }
+}
+
+#[aoc(day14, part2)]
+pub fn solve_part2(states: &[State]) -> u64 {
+    let mut memory = Memory::default();
 
     for state in states.iter() {
-        let floating = state.masks.iter().filter(|m| m.x).copied().collect_vec();
-        for (i, val) in state.mem.iter() {
-            let n = apply_masks(&state.masks, i);
-            do_it_n_times(&floating, n)
-                .iter()
-                .for_each(|x| { let _ = h.insert(*x, *val); });
+        for &(i, val) in state.mem.iter() {
+            memory.write(Pattern::decode(&state.masks, i), val);
         }
     }
 
-    h.values().sum()
+    memory.sum()
 }
 
 #[cfg(test)]
@@ -236,4 +290,38 @@ mem[26] = 1";
     fn test_part2() {
         assert_eq!(solve_part2(&gen(get_input1())), 208);
     }
+
+    #[test]
+    fn test_memory() {
+        let states = gen(get_input1());
+        let p = Pattern::decode(&states[0].masks, 42);
+        assert_eq!(p, Pattern { fixed: 0b011010, floating: 0b100001 });
+        assert_eq!(p.size(), 4);
+        assert!(p.contains(59) && p.contains(26) && !p.contains(42));
+
+        let q = Pattern::decode(&states[1].masks, 26);
+        let pieces = p.subtract(&q);
+        assert_eq!(pieces.iter().map(|p| p.size()).sum::<u64>(), 2);
+        assert!(pieces.iter().all(|p| !p.contains(26) && !p.contains(27)));
+        assert_eq!(p.subtract(&Pattern { fixed: 0, floating: 0 }), vec![p]);
+
+        let mut memory = Memory::default();
+        memory.write(p, 100);
+        memory.write(q, 1);
+        assert_eq!((memory.get(58), memory.get(27), memory.get(1)), (100, 1, 0));
+        memory.write(q, 0);
+        assert_eq!(memory.sum(), 200);
+
+        // 30 floating bits, 24 of them overwritten, then 6 of those zeroed and
+        // 6 new ones at odd addresses.
+        let input = "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXX000000
+mem[0] = 3
+mask = 000000XXXXXXXXXXXXXXXXXXXXXXXX000000
+mem[0] = 5
+mask = 000000000000000000XXXXXX000000000000
+mem[1] = 7
+mem[0] = 0";
+        let expected = 3 * ((1 << 30) - (1 << 24)) + 5 * ((1 << 24) - (1 << 6)) + 7 * (1 << 6);
+        assert_eq!(solve_part2(&gen(input)), expected);
+    }
 }
\ No newline at end of file
@@ -1,72 +1,260 @@
-use nom::bytes::complete::{tag, take_while};
-use nom::sequence::{tuple, separated_pair, pair};
-use nom::character::complete::{space1, digit1, multispace0, char, newline};
-use nom::combinator::{recognize, map_res, opt};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while1};
+use nom::sequence::{separated_pair, terminated};
+use nom::character::complete::{space0, space1, digit1, char};
+use nom::combinator::{all_consuming, recognize, map, map_res};
 use nom::IResult;
-use nom::multi::many1;
+use nom::multi::separated_list1;
 use itertools::Itertools;
-use std::ops::Not;
-use nom::lib::std::collections::HashSet;
-
-#[derive(Debug, Copy, Clone)]
+use regex::Regex;
+use std::fmt;
+use std::iter;
+use std::str::FromStr;
+use crate::assignment::{AssignmentError, Problem};
+
+// Inclusive bounds.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Range {
     lower: usize,
     upper: usize,
 }
 
-#[derive(Debug, Clone)]
+// Sorted ranges that neither overlap nor touch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalSet {
+    ranges: Vec<Range>,
+}
+
+// The ranges as written, and merged for lookups. The line it was read from
+// is kept to write it back.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Field {
     name: String,
-    ranges: [Range; 2],
+    rules: Vec<Range>,
+    ranges: IntervalSet,
+    line: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Ticket {
-    values: Vec<usize>
+    values: Vec<usize>,
+    line: String,
 }
 
-#[derive(Debug, Clone)]
-pub struct State {
+// The whole input, written back as it was read: fields and tickets from
+// their source lines, and the number of blank lines before, between and
+// after the sections. The headers are kept as they are, only their position
+// matters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TicketNotes {
     fields: Vec<Field>,
+    headers: [String; 2],
     ticket: Ticket,
     nearby: Vec<Ticket>,
+    newline: &'static str,
+    blanks: [usize; 4],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotesError {
+    MissingSection(usize),
+    BadField(String),
+    BadHeader(String),
+    BadTicket(String),
+    OwnTicketCount(usize),
+    TicketLength { line: String, expected: usize },
+}
+
+impl fmt::Display for NotesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotesError::MissingSection(n) => write!(f, "{} sections, expected fields, your ticket and nearby tickets", n),
+            NotesError::BadField(l) => write!(f, "invalid field {:?}", l),
+            NotesError::BadHeader(l) => write!(f, "invalid header {:?}", l),
+            NotesError::BadTicket(l) => write!(f, "invalid ticket {:?}", l),
+            NotesError::OwnTicketCount(n) => write!(f, "{} tickets under the second header, expected 1", n),
+            NotesError::TicketLength { line, expected } =>
+                write!(f, "ticket {:?} doesn't have {} values", line, expected),
+        }
+    }
+}
+
+// Which fields to keep, by name.
+#[derive(Debug, Clone)]
+pub enum Selector {
+    Prefix(String),
+    Regex(Regex),
+    Names(Vec<String>),
+}
+
+impl Selector {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Selector::Prefix(p) => name.starts_with(&p[..]),
+            Selector::Regex(r) => r.is_match(name),
+            Selector::Names(names) => names.iter().any(|n| n == name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidValue {
+    pub column: usize,
+    pub value: usize,
+    pub nearest: Option<Range>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TicketReport {
+    pub ticket: usize,
+    pub invalid: Vec<InvalidValue>,
 }
 
 impl Range {
+    pub fn new(lower: usize, upper: usize) -> Range {
+        Range { lower, upper }
+    }
+
     fn in_bounds(&self, value: &usize) -> bool {
         *value >= self.lower && *value <= self.upper
     }
+
+    fn distance(&self, value: usize) -> usize {
+        if value < self.lower {
+            self.lower - value
+        } else {
+            value.saturating_sub(self.upper)
+        }
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.lower == self.upper {
+            write!(f, "{}", self.lower)
+        } else {
+            write!(f, "{}-{}", self.lower, self.upper)
+        }
+    }
+}
+
+impl IntervalSet {
+    pub fn new(ranges: impl IntoIterator<Item = Range>) -> IntervalSet {
+        let mut merged: Vec<Range> = Vec::new();
+        for r in ranges.into_iter().filter(|r| r.lower <= r.upper).sorted() {
+            match merged.last_mut() {
+                Some(last) if r.lower <= last.upper.saturating_add(1) => last.upper = last.upper.max(r.upper),
+                _ => merged.push(r),
+            }
+        }
+        IntervalSet { ranges: merged }
+    }
+
+    pub fn ranges(&self) -> &[Range] {
+        &self.ranges
+    }
+
+    // Index of the first range that doesn't end before the value.
+    fn position(&self, value: usize) -> usize {
+        self.ranges.partition_point(|r| r.upper < value)
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        self.ranges.get(self.position(value)).map(|r| r.in_bounds(&value)) == Some(true)
+    }
+
+    // Ties go to the lower range.
+    pub fn nearest(&self, value: usize) -> Option<Range> {
+        let i = self.position(value);
+        let before = i.checked_sub(1).and_then(|i| self.ranges.get(i));
+        [before, self.ranges.get(i)]
+            .iter()
+            .flatten()
+            .min_by_key(|r| r.distance(value))
+            .map(|&&r| r)
+    }
+
+    pub fn union<'a>(sets: impl IntoIterator<Item = &'a IntervalSet>) -> IntervalSet {
+        IntervalSet::new(sets.into_iter().flat_map(|s| s.ranges.iter().copied()))
+    }
 }
 
 impl Field {
+    pub fn new(name: &str, rules: Vec<Range>) -> Field {
+        let ranges = IntervalSet::new(rules.iter().copied());
+        let line = format!("{}: {}", name, rules.iter().join(" or "));
+        Field { name: name.to_string(), rules, ranges, line }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     fn in_bounds(&self, value: &usize) -> bool {
-        self.ranges.iter().any(|r| r.in_bounds(value))
+        self.ranges.contains(*value)
     }
 }
 
 impl Ticket {
-    fn is_valid(&self, fields: &[Field]) -> bool {
-        self.values
-            .iter()
-            .all(|val| fields
-                .iter()
-                .any(|f| f.in_bounds(val))
-            )
+    fn is_valid(&self, valid: &IntervalSet) -> bool {
+        self.values.iter().all(|&v| valid.contains(v))
     }
 
-    fn get_invalid_values(&self, fields: &[Field]) -> Vec<usize> {
+    fn get_invalid_values(&self, valid: &IntervalSet) -> Vec<InvalidValue> {
         self.values
             .iter()
-            .filter(|val| fields
-                .iter()
-                .all(|f| f.in_bounds(val).not())// {
-            )
-            .copied()
+            .enumerate()
+            .filter(|&(_, &value)| !valid.contains(value))
+            .map(|(column, &value)| InvalidValue { column, value, nearest: valid.nearest(value) })
             .collect()
     }
 }
 
-impl State {
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.line)
+    }
+}
+
+impl fmt::Display for Ticket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.line)
+    }
+}
+
+impl fmt::Display for TicketReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.invalid.is_empty() {
+            return write!(f, "ticket {}: valid", self.ticket);
+        }
+        write!(f, "ticket {}:", self.ticket)?;
+        for v in self.invalid.iter() {
+            write!(f, " {} in column {}", v.value, v.column)?;
+            if let Some(r) = v.nearest {
+                write!(f, " (nearest {})", r)?;
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+impl TicketNotes {
+    // Values that fit at least one field.
+    pub fn valid_values(&self) -> IntervalSet {
+        IntervalSet::union(self.fields.iter().map(|f| &f.ranges))
+    }
+
+    // One report per nearby ticket, in input order.
+    pub fn validate(&self) -> Vec<TicketReport> {
+        let valid = self.valid_values();
+        self.nearby
+            .iter()
+            .enumerate()
+            .map(|(ticket, t)| TicketReport { ticket, invalid: t.get_invalid_values(&valid) })
+            .collect()
+    }
+
     fn get_columns(&self) -> Vec<Vec<usize>> {
         let mut ret: Vec<Vec<usize>> = Vec::new();
 
@@ -87,148 +275,196 @@ fn parse_num(s: &str) -> IResult<&str, usize> {
     map_res(digit1, parse_num)(s)
 }
 
-fn parse_fields(s: &str) -> IResult<&str, Vec<Field>> {
-    fn field(s: &str) -> IResult<&str, String> {
-        let not_separator = |c: char| c != ':';
-        let (s, field) = take_while(not_separator)(s)?;
-        Ok((&s[1..], field.to_string()))
-    }
-
+fn parse_field(s: &str) -> IResult<&str, Field> {
+    // Either "lower-upper" or a single value.
     fn range(s: &str) -> IResult<&str, Range> {
-        let (s, (lower, upper)) = separated_pair(parse_num, tag("-"), parse_num)(s)?;
-        Ok((s, Range { lower, upper }))
-    }
-
-    fn ranges(s: &str) -> IResult<&str, (Range, Range)> {
-        let or = recognize(separated_pair(space1, tag("or"), space1));
-        let (s, _) = multispace0(s)?;
-        let (s, (n, m)) = separated_pair(range, or, range)(s)?;
-        Ok((s, (n, m)))
+        alt((
+            map(separated_pair(parse_num, tag("-"), parse_num), |(lower, upper)| Range { lower, upper }),
+            map(parse_num, |n| Range { lower: n, upper: n }),
+        ))(s)
     }
 
-    let (s, fields) = many1(tuple((field, ranges, newline)))(s)?;
-    let fields = fields
-        .iter()
-        .map(|(name, (r1, r2), _)|
-            Field { name: name.clone(), ranges: [*r1, *r2] }
-        ).collect_vec();
-
-    Ok((s, fields))
+    let or = recognize(separated_pair(space1, tag("or"), space1));
+    let (s, name) = terminated(take_while1(|c: char| c != ':'), terminated(char(':'), space0))(s)?;
+    let (s, rules) = separated_list1(or, range)(s)?;
+    Ok((s, Field::new(name, rules)))
 }
 
 fn parse_ticket(s: &str) -> IResult<&str, Ticket> {
-    let (s, _) = multispace0(s)?;
-    let (s, values) = many1(tuple((parse_num, opt(char(',')))))(s)?;
-    let values = values
-        .iter()
-        .map(|(n, _)| *n)
-        .collect();
-    Ok((s, Ticket { values }))
+    map(separated_list1(char(','), parse_num), |values: Vec<usize>| {
+        let line = values.iter().join(",");
+        Ticket { values, line }
+    })(s)
 }
 
-fn parse_mine_ticket(s: &str) -> IResult<&str, Ticket> {
-    let (s, _) = multispace0(s)?;
-    let (s, _) = pair(tag("your ticket:"), newline)(s)?;
-    parse_ticket(s)
+fn parse_header(s: &str) -> Result<String, NotesError> {
+    if s.ends_with(':') && !s.contains(',') {
+        Ok(s.to_string())
+    } else {
+        Err(NotesError::BadHeader(s.to_string()))
+    }
 }
 
-fn parse_nearby_tickets(s: &str) -> IResult<&str, Vec<Ticket>> {
-    let (s, _) = multispace0(s)?;
-    let (s, _) = pair(tag("nearby tickets:"), newline)(s)?;
-    many1(parse_ticket)(s)
-}
+impl FromStr for TicketNotes {
+    type Err = NotesError;
+
+    fn from_str(s: &str) -> Result<TicketNotes, NotesError> {
+        let newline = if s.contains("\r\n") { "\r\n" } else { "\n" };
+
+        // Runs of blank lines alternate with the sections.
+        let mut blanks = vec![0];
+        let mut sections: Vec<Vec<&str>> = Vec::new();
+        for l in s.split(newline) {
+            if l.is_empty() {
+                if blanks.len() == sections.len() {
+                    blanks.push(0);
+                }
+                *blanks.last_mut().unwrap() += 1;
+            } else {
+                if blanks.len() > sections.len() {
+                    sections.push(Vec::new());
+                }
+                sections.last_mut().unwrap().push(l);
+            }
+        }
+        if sections.len() != 3 {
+            return Err(NotesError::MissingSection(sections.len()));
+        }
+        blanks.resize(4, 0);
+
+        let fields = sections[0]
+            .iter()
+            .map(|&l| all_consuming(parse_field)(l)
+                .map(|(_, f)| Field { line: l.to_string(), ..f })
+                .map_err(|_| NotesError::BadField(l.to_string())))
+            .collect::<Result<Vec<Field>, _>>()?;
+
+        let mut tickets = Vec::new();
+        let mut headers = Vec::new();
+        for section in sections[1..].iter() {
+            headers.push(parse_header(section[0])?);
+            tickets.push(
+                section[1..]
+                    .iter()
+                    .map(|&l| all_consuming(parse_ticket)(l)
+                        .map(|(_, t)| Ticket { line: l.to_string(), ..t })
+                        .map_err(|_| NotesError::BadTicket(l.to_string())))
+                    .collect::<Result<Vec<Ticket>, _>>()?,
+            );
+        }
+
+        let nearby = tickets.pop().unwrap();
+        let mut own = tickets.pop().unwrap();
+        if own.len() != 1 {
+            return Err(NotesError::OwnTicketCount(own.len()));
+        }
+        let ticket = own.pop().unwrap();
+        if let Some(t) = nearby.iter().find(|t| t.values.len() != ticket.values.len()) {
+            return Err(NotesError::TicketLength { line: t.to_string(), expected: ticket.values.len() });
+        }
 
-fn parse(s: &str) -> IResult<&str, State> {
-    let (s, fields) = parse_fields(s)?;
-    let (s, ticket) = parse_mine_ticket(s)?;
-    let (s, nearby) = parse_nearby_tickets(s)?;
-    let (s, _) = multispace0(s)?;
+        Ok(TicketNotes {
+            fields,
+            headers: [headers[0].clone(), headers[1].clone()],
+            ticket,
+            nearby,
+            newline,
+            blanks: [blanks[0], blanks[1], blanks[2], blanks[3]],
+        })
+    }
+}
 
-    let state = State {
-        fields,
-        ticket,
-        nearby,
-    };
-    Ok((s, state))
+impl fmt::Display for TicketNotes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            self.fields.iter().map(|field| &field.line[..]).collect_vec(),
+            vec![&self.headers[0][..], &self.ticket.line[..]],
+            iter::once(&self.headers[1][..]).chain(self.nearby.iter().map(|t| &t.line[..])).collect_vec(),
+        ];
+        let mut lines = Vec::new();
+        for (blanks, section) in self.blanks.iter().zip(sections.iter()) {
+            lines.extend(iter::repeat_n("", *blanks));
+            lines.extend(section.iter().copied());
+        }
+        lines.extend(iter::repeat_n("", self.blanks[3]));
+        write!(f, "{}", lines.join(self.newline))
+    }
 }
 
 #[aoc_generator(day16)]
-pub fn gen(input: &str) -> State {
-    match parse(input) {
-        Ok(("", state)) => state,
-        _ => panic!("Parsing error")
-    }
+pub fn gen(input: &str) -> TicketNotes {
+    input.parse().unwrap_or_else(|e| panic!("Parsing error: {}", e))
 }
 
 #[aoc(day16, part1)]
-pub fn solve_part1(state: &State) -> usize {
-    state.nearby
+pub fn solve_part1(notes: &TicketNotes) -> usize {
+    notes.validate()
         .iter()
-        .flat_map(|ticket| ticket.get_invalid_values(&state.fields))
+        .flat_map(|r| r.invalid.iter())
+        .map(|v| v.value)
         .sum()
 }
 
-fn filter_fields<'a>(vals: &[usize], fields: &'a [Field]) -> Vec<&'a Field> {
-    fields
-        .iter()
-        .filter(|f| {
-            vals
+// Field of each column, the only one its values fit once the other columns
+// have theirs.
+fn find_fields_names<'a>(columns: &[Vec<usize>], fields: &'a [Field]) -> Result<Vec<&'a Field>, AssignmentError> {
+    let mut problem = Problem::new(fields.len());
+    for column in columns {
+        problem.add_item(
+            fields
                 .iter()
-                .all(|v| f.in_bounds(v))
-        })
-        .collect()
-}
+                .enumerate()
+                .filter(|(_, f)| column.iter().all(|v| f.in_bounds(v)))
+                .map(|(i, _)| i),
+        );
+    }
 
-fn find_fields_names<'a>(fields: &[(usize, Vec<&'a Field>)]) -> Vec<(usize, &'a Field)> {
-    let mut ret: Vec<(usize, &Field)> = Vec::new();
-    let mut previous: HashSet<String> = HashSet::new();
+    Ok(problem.solve()?.into_iter().map(|i| &fields[i]).collect())
+}
 
-    for (i, f) in fields {
-        let field = f
+impl TicketNotes {
+    // The field of each column, using the nearby tickets without invalid
+    // values.
+    pub fn columns(&self) -> Result<Vec<&Field>, AssignmentError> {
+        let valid = self.valid_values();
+        let nearby = self.nearby
             .iter()
-            .filter(|f| previous.contains(&f.name[..]).not())
-            .collect_vec();
-
-        assert_eq!(field.len(), 1);
-        previous.insert(field[0].name.clone());
-        ret.push((*i, field[0]));
+            .filter(|ticket| ticket.is_valid(&valid))
+            .cloned()
+            .collect();
+        let notes = TicketNotes {
+            nearby,
+            ..self.clone()
+        };
+
+        find_fields_names(&notes.get_columns(), &self.fields)
     }
 
-    ret
-}
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
 
-#[aoc(day16, part2)]
-pub fn solve_part2(state: &State) -> usize {
-    let nearby = state.nearby
-        .iter()
-        .filter(|ticket| ticket.is_valid(&state.fields))
-        .cloned()
-        .collect();
-    let state = State {
-        nearby,
-        ..state.clone()
-    };
-
-    let columns = state.get_columns();
-    let columns_fields = columns
-        .iter()
-        .map(|c| filter_fields(c, &state.fields))
-        .enumerate()
-        .sorted_by(|(_, x), (_, y)| x.len().cmp(&y.len()))
-        .collect_vec();
-    let names = find_fields_names(&columns_fields);
-    let indexes = names
-        .iter()
-        .filter(|(_, f)| f.name.starts_with("departure"))
-        .map(|(i, _)| i)
-        .collect_vec();
+    // Column and value on your ticket of the selected fields.
+    pub fn select(&self, selector: &Selector) -> Result<Vec<(usize, &Field, usize)>, AssignmentError> {
+        Ok(self
+            .columns()?
+            .into_iter()
+            .enumerate()
+            .filter(|(_, f)| selector.matches(f.name()))
+            .map(|(i, f)| (i, f, self.ticket.values[i]))
+            .collect())
+    }
 
-    let mut product = 1;
-    for i in indexes {
-        product *= state.ticket.values[*i];
+    pub fn product(&self, selector: &Selector) -> Result<usize, AssignmentError> {
+        Ok(self.select(selector)?.iter().map(|&(_, _, v)| v).product())
     }
+}
 
-    product
+#[aoc(day16, part2)]
+pub fn solve_part2(notes: &TicketNotes) -> usize {
+    notes
+        .product(&Selector::Prefix("departure".to_string()))
+        .unwrap_or_else(|e| panic!("No field order: {}", e))
 }
 
 #[cfg(test)]
@@ -278,4 +514,92 @@ nearby tickets:
     fn test_part2() {
         solve_part2(&gen(get_input2()));
     }
+
+    #[test]
+    fn test_interval_set() {
+        let set = IntervalSet::new(vec![
+            Range::new(5, 7), Range::new(1, 3), Range::new(4, 4), Range::new(10, 12), Range::new(11, 20),
+        ]);
+        assert_eq!(set.ranges(), &[Range::new(1, 7), Range::new(10, 20)]);
+        assert!(set.contains(1) && set.contains(7) && set.contains(15));
+        assert!(!set.contains(0) && !set.contains(8) && !set.contains(21));
+        assert_eq!(set.nearest(8), Some(Range::new(1, 7)));
+        assert_eq!(set.nearest(9), Some(Range::new(10, 20)));
+        assert_eq!(set.nearest(100), Some(Range::new(10, 20)));
+        assert_eq!(IntervalSet::new(vec![]).nearest(1), None);
+
+        let notes = gen("a: 1 or 3-4 or 8\nb: 10-12\n\nyour ticket:\n1,10\n\nnearby tickets:\n2,8");
+        assert_eq!(notes.valid_values().ranges(), &[Range::new(1, 1), Range::new(3, 4), Range::new(8, 8), Range::new(10, 12)]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let reports = gen(get_input()).validate();
+        assert!(reports[0].invalid.is_empty());
+        assert_eq!(reports[1].invalid, vec![InvalidValue { column: 1, value: 4, nearest: Some(Range::new(1, 3)) }]);
+        assert_eq!(reports[2].invalid[0].nearest, Some(Range::new(13, 50)));
+        assert_eq!(reports[3].to_string(), "ticket 3: 12 in column 2 (nearest 5-11);");
+        assert_eq!(reports[0].to_string(), "ticket 0: valid");
+    }
+
+    #[test]
+    fn test_notes() {
+        for input in [get_input(), get_input2()].iter() {
+            assert_eq!(gen(input).to_string(), *input);
+        }
+        let input = "size: 4 or 1-2 or 6-9\n\nmine:\n3\n\nothers:\n";
+        assert_eq!(gen(input).to_string(), input);
+        assert_eq!(gen(input).field("size").unwrap().to_string(), "size: 4 or 1-2 or 6-9");
+        for input in [
+            "x: 5-5 or 7-9\n\nyour ticket:\n07\n\nnearby tickets:\n6",
+            "a: 1-2  or  3-4\n\nmine:\n1\n\nothers:\n3\n\n",
+            "a: 1-2 or 3-4\r\n\r\nmine:\r\n1\r\n\r\nothers:\r\n3\r\n",
+            "\na: 1\n\n\nmine:\n1\n\nothers:",
+        ].iter() {
+            assert_eq!(gen(input).to_string(), *input);
+        }
+        let notes = gen("x: 5-5 or 7-9\r\n\r\nyour ticket:\r\n07\r\n\r\nnearby tickets:\r\n6\r\n\r\n");
+        assert_eq!(notes.field("x").unwrap().rules, vec![Range::new(5, 5), Range::new(7, 9)]);
+        assert_eq!(notes.ticket.values, vec![7]);
+        assert_eq!(Field::new("x", vec![Range::new(5, 5), Range::new(7, 9)]).to_string(), "x: 5 or 7-9");
+
+        assert_eq!("a: 1-2\n\nyours:\n1".parse::<TicketNotes>(), Err(NotesError::MissingSection(2)));
+        assert_eq!("a 1-2\n\nyours:\n1\n\nnearby:\n2".parse::<TicketNotes>(),
+                   Err(NotesError::BadField("a 1-2".to_string())));
+        assert_eq!("a: 1-2\n\nyours\n1\n\nnearby:\n2".parse::<TicketNotes>(),
+                   Err(NotesError::BadHeader("yours".to_string())));
+        assert_eq!("a: 1-2\n\nyours:\n1\n\nnearby:\n2,".parse::<TicketNotes>(),
+                   Err(NotesError::BadTicket("2,".to_string())));
+        assert_eq!("a: 1-2\n\nyours:\n\nnearby:\n2".parse::<TicketNotes>(), Err(NotesError::OwnTicketCount(0)));
+        assert_eq!("a: 1-2\n\nyours:\n1\n2\n\nnearby:\n2".parse::<TicketNotes>(),
+                   Err(NotesError::OwnTicketCount(2)));
+        assert_eq!("a: 1-2\n\nyours:\n1\n\nnearby:\n2,1".parse::<TicketNotes>(),
+                   Err(NotesError::TicketLength { line: "2,1".to_string(), expected: 1 }));
+    }
+
+    #[test]
+    fn test_select() {
+        let notes = gen(get_input2());
+        let names = |sel: &Selector| notes.select(sel).unwrap().iter().map(|&(i, f, v)| (i, f.name(), v)).collect_vec();
+
+        assert_eq!(names(&Selector::Prefix("s".to_string())), vec![(2, "seat", 13)]);
+        assert_eq!(names(&Selector::Regex(Regex::new("^(row|seat)$").unwrap())), vec![(0, "row", 11), (2, "seat", 13)]);
+        assert_eq!(names(&Selector::Names(vec!["class".to_string(), "nothing".to_string()])), vec![(1, "class", 12)]);
+        assert_eq!(notes.product(&Selector::Prefix(String::new())), Ok(11 * 12 * 13));
+        assert_eq!(notes.product(&Selector::Names(vec![])), Ok(1));
+    }
+
+    #[test]
+    fn test_fields_names() {
+        let notes = gen(get_input2());
+        let names = find_fields_names(&notes.get_columns(), &notes.fields).unwrap();
+        assert_eq!(names.iter().map(|f| &f.name[..]).collect_vec(), vec!["row", "class", "seat"]);
+
+        // Without the third nearby ticket class and seat fit both last columns.
+        let columns = vec![vec![3, 15], vec![9, 1], vec![18, 5]];
+        assert_eq!(find_fields_names(&columns, &notes.fields).unwrap_err(),
+                   AssignmentError::Ambiguous { item: 1, values: (0, 2) });
+        let columns = vec![vec![3], vec![2], vec![2, 3]];
+        assert_eq!(find_fields_names(&columns, &notes.fields).unwrap_err(), AssignmentError::Infeasible(2));
+    }
 }
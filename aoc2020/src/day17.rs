@@ -1,142 +1,412 @@
-use nom::lib::std::collections::HashSet;
-use std::ops::Not;
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Point {
-    x: isize,
-    y: isize,
-    z: isize,
-    w: Option<isize>,
-}
-
-trait HashUtils {
-    fn count_active_neighbors(&self, p: &Point) -> usize;
-    fn next_cube_active(&self, p: &Point) -> bool;
-    fn next_state(&self) -> Self;
-}
-
-impl HashUtils for HashSet<Point> {
-    fn count_active_neighbors(&self, p: &Point) -> usize {
-        let mut actives = 0;
-        let w_range = if p.w.is_none() { 1..=1 } else { -1..=1 };
-
-        for w in w_range {
-            for x in -1..=1 {
-                for y in -1..=1 {
-                    for z in -1..=1 {
-                        if (p.w.is_some() && w == 0 && x == 0 && y == 0 && z == 0 ||
-                            (p.w.is_none() && x == 0 && y == 0 && z == 0)).not() {
-                            let p = Point {
-                                x: p.x + x,
-                                y: p.y + y,
-                                z: p.z + z,
-                                w: p.w.map(|n| n + w),
-                            };
-                            if self.contains(&p) {
-                                actives += 1;
-                            }
-                        }
+use nom::lib::std::collections::{HashMap, HashSet};
+use crate::animation::Frame;
+use crate::cellular::Rule;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Point<const N: usize>([isize; N]);
+
+fn offsets<const N: usize>() -> Vec<[isize; N]> {
+    let mut ret = vec![[0; N]];
+
+    for axis in 0..N {
+        ret = ret
+            .into_iter()
+            .flat_map(|p| (-1..=1).map(move |d| {
+                let mut p = p;
+                p[axis] = d;
+                p
+            }))
+            .collect();
+    }
+
+    ret.retain(|p| p.iter().any(|&d| d != 0));
+    ret
+}
+
+fn factorial(n: usize) -> usize {
+    (1..=n).product()
+}
+
+// Cubes in N dimensions. When the starting state is a flat slice, every axis
+// after the first `free` ones is symmetric (sign and order of the coordinates
+// don't matter), so only points with those coordinates positive and sorted
+// are stored.
+#[derive(Debug, Clone)]
+pub struct Life<const N: usize> {
+    active: HashSet<Point<N>>,
+    rule: Rule,
+    free: usize,
+    offsets: Vec<[isize; N]>,
+}
+
+impl<const N: usize> Life<N> {
+    pub fn new(active: impl IntoIterator<Item = Point<N>>, rule: Rule) -> Life<N> {
+        Life {
+            active: active.into_iter().collect(),
+            rule,
+            free: N,
+            offsets: offsets(),
+        }
+    }
+
+    // Cells of a 2D slice, every other coordinate being 0.
+    pub fn from_slice(cells: &[(isize, isize)], rule: Rule) -> Life<N> {
+        let active = cells
+            .iter()
+            .map(|&(x, y)| {
+                let mut p = [0; N];
+                p[0] = x;
+                if N > 1 {
+                    p[1] = y;
+                }
+                Point(p)
+            });
+
+        Life {
+            free: N.min(2),
+            ..Life::new(active, rule)
+        }
+    }
+
+    pub fn without_folding(&self) -> Life<N> {
+        Life::new(self.points(), self.rule.clone())
+    }
+
+    fn canonical(&self, mut p: [isize; N]) -> Point<N> {
+        for c in p[self.free..].iter_mut() {
+            *c = c.abs();
+        }
+        p[self.free..].sort_unstable();
+        Point(p)
+    }
+
+    // Number of points a stored one stands for.
+    fn multiplicity(&self, p: &Point<N>) -> usize {
+        let folded = &p.0[self.free..];
+        let mut counts: HashMap<isize, usize> = HashMap::new();
+        for &c in folded {
+            *counts.entry(c).or_default() += 1;
+        }
+
+        let perms = factorial(folded.len()) / counts.values().map(|&n| factorial(n)).product::<usize>();
+        perms << folded.iter().filter(|&&c| c != 0).count()
+    }
+
+    fn count_active_neighbors(&self, p: &Point<N>) -> usize {
+        self.offsets
+            .iter()
+            .filter(|o| {
+                let mut q = p.0;
+                for (c, d) in q.iter_mut().zip(o.iter()) {
+                    *c += d;
+                }
+                self.active.contains(&self.canonical(q))
+            })
+            .count()
+    }
+
+    pub fn step(&mut self) {
+        let candidates: HashSet<Point<N>> = self.active
+            .iter()
+            .flat_map(|p| self.offsets
+                .iter()
+                .map(move |o| {
+                    let mut q = p.0;
+                    for (c, d) in q.iter_mut().zip(o.iter()) {
+                        *c += d;
+                    }
+                    q
+                })
+                .chain(std::iter::once(p.0)))
+            .map(|q| self.canonical(q))
+            .collect();
+
+        self.active = candidates
+            .into_iter()
+            .filter(|p| self.rule.next(self.active.contains(p), self.count_active_neighbors(p)))
+            .collect();
+    }
+
+    pub fn run(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.step();
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.active.iter().map(|p| self.multiplicity(p)).sum()
+    }
+
+    // Every active point, folded ones included.
+    pub fn points(&self) -> Vec<Point<N>> {
+        let mut ret: HashSet<Point<N>> = HashSet::new();
+
+        for p in self.active.iter() {
+            let mut todo = vec![p.0];
+            while let Some(q) = todo.pop() {
+                if !ret.insert(Point(q)) {
+                    continue;
+                }
+                for i in self.free..N {
+                    let mut r = q;
+                    r[i] = -r[i];
+                    todo.push(r);
+                    if i + 1 < N {
+                        let mut r = q;
+                        r.swap(i, i + 1);
+                        todo.push(r);
                     }
                 }
             }
         }
 
-        actives
+        ret.into_iter().collect()
     }
+}
 
-    fn next_cube_active(&self, p: &Point) -> bool {
-        let n = self.count_active_neighbors(p);
-        let active = self.contains(p);
+// Cubes in a flat array, grown by one cell on every side each cycle since
+// nothing can appear further away. Axes after the first `free` ones only
+// keep their non-negative half and are mirrored around 0.
+#[derive(Debug, Clone)]
+pub struct Dense<const N: usize> {
+    cells: Vec<u8>,
+    dims: [usize; N],
+    origin: [isize; N],
+    free: usize,
+    rule: Rule,
+}
+
+impl<const N: usize> Dense<N> {
+    pub fn from_slice(cells: &[(isize, isize)], rule: Rule) -> Dense<N> {
+        let free = N.min(2);
+        let mut dims = [1; N];
+        let mut origin = [0; N];
+
+        for axis in 0..free {
+            let coords = cells.iter().map(|&(x, y)| if axis == 0 { x } else { y });
+            let min = coords.clone().min().unwrap_or(0);
+            let max = coords.max().unwrap_or(0);
+            origin[axis] = min;
+            dims[axis] = (max - min + 1) as usize;
+        }
 
-        if active && (n != 2 && n != 3) {
-            false
-        } else if active.not() && n == 3 {
-            true
-        } else {
-            active
+        let mut dense = Dense {
+            cells: vec![0; dims.iter().product()],
+            dims,
+            origin,
+            free,
+            rule,
+        };
+        for &(x, y) in cells {
+            let mut p = [0; N];
+            p[0] = x - origin[0];
+            if N > 1 {
+                p[1] = y - origin[1];
+            }
+            let i = dense.index(&p);
+            dense.cells[i] = 1;
         }
+
+        dense
     }
 
-    fn next_state(&self) -> Self {
-        let mut h: Self = HashSet::new();
+    fn strides(dims: &[usize; N]) -> [usize; N] {
+        let mut strides = [1; N];
+        for axis in (0..N.saturating_sub(1)).rev() {
+            strides[axis] = strides[axis + 1] * dims[axis + 1];
+        }
+        strides
+    }
 
-        self
+    fn index(&self, p: &[isize; N]) -> usize {
+        Dense::strides(&self.dims)
             .iter()
-            .for_each(|p| {
-                let w_range = if p.w.is_none() { 1..=1 } else { -1..=1 };
-                for w in w_range {
-                    for x in -1..=1 {
-                        for y in -1..=1 {
-                            for z in -1..=1 {
-                                let p = Point {
-                                    x: p.x + x,
-                                    y: p.y + y,
-                                    z: p.z + z,
-                                    w: p.w.map(|n| n + w),
-                                };
-                                if h.contains(&p).not() && self.next_cube_active(&p) {
-                                    h.insert(p);
-                                }
-                            }
-                        }
-                    }
+            .zip(p.iter())
+            .map(|(s, &c)| s * c as usize)
+            .sum()
+    }
+
+    fn grow(&mut self) {
+        let mut dims = self.dims;
+        let mut shift = [0; N];
+        for axis in 0..N {
+            if axis < self.free {
+                dims[axis] += 2;
+                shift[axis] = 1;
+                self.origin[axis] -= 1;
+            } else {
+                dims[axis] += 1;
+            }
+        }
+
+        let old = Dense::strides(&self.dims);
+        let new = Dense::strides(&dims);
+        let mut cells = vec![0; dims.iter().product()];
+        for (i, &c) in self.cells.iter().enumerate() {
+            if c != 0 {
+                let j: usize = (0..N).map(|a| ((i / old[a]) % self.dims[a] + shift[a]) * new[a]).sum();
+                cells[j] = c;
+            }
+        }
+
+        self.cells = cells;
+        self.dims = dims;
+    }
+
+    // 3^N box sum done one axis at a time.
+    fn box_sums(&self) -> Vec<u16> {
+        let strides = Dense::strides(&self.dims);
+        let mut sums: Vec<u16> = self.cells.iter().map(|&c| c as u16).collect();
+
+        for (axis, (&stride, &len)) in strides.iter().zip(self.dims.iter()).enumerate() {
+            let mirrored = axis >= self.free;
+            let prev = sums.clone();
+
+            for (i, sum) in sums.iter_mut().enumerate() {
+                let pos = (i / stride) % len;
+                if pos > 0 {
+                    *sum += prev[i - stride];
+                } else if mirrored && len > 1 {
+                    *sum += prev[i + stride];
                 }
-            });
+                if pos + 1 < len {
+                    *sum += prev[i + stride];
+                }
+            }
+        }
+
+        sums
+    }
 
-        h
+    fn multiplicity(&self, i: usize, strides: &[usize; N]) -> usize {
+        let coord = |a: usize| (i / strides[a]) % self.dims[a];
+        1 << (self.free..N).filter(|&a| coord(a) != 0).count()
     }
 }
 
-#[aoc_generator(day17)]
-pub fn gen(input: &str) -> HashSet<Point> {
-    let mut h: HashSet<Point> = HashSet::new();
+pub trait Automaton {
+    fn step(&mut self);
+    fn count(&self) -> usize;
+
+    fn run(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.step();
+        }
+    }
+}
+
+impl<const N: usize> Automaton for Life<N> {
+    fn step(&mut self) {
+        Life::step(self)
+    }
+
+    fn count(&self) -> usize {
+        Life::count(self)
+    }
+}
+
+impl<const N: usize> Automaton for Dense<N> {
+    fn step(&mut self) {
+        self.grow();
+        let sums = self.box_sums();
+
+        for (c, &sum) in self.cells.iter_mut().zip(sums.iter()) {
+            let active = *c != 0;
+            *c = self.rule.next(active, (sum - *c as u16) as usize) as u8;
+        }
+    }
+
+    fn count(&self) -> usize {
+        let strides = Dense::strides(&self.dims);
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|&(_, &c)| c != 0)
+            .map(|(i, _)| self.multiplicity(i, &strides))
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Sparse,
+    Dense,
+}
 
+pub fn simulate<const N: usize>(cells: &[(isize, isize)], rule: Rule, cycles: usize, backend: Backend) -> usize {
+    let mut automaton: Box<dyn Automaton> = match backend {
+        Backend::Sparse => Box::new(Life::<N>::from_slice(cells, rule)),
+        Backend::Dense => Box::new(Dense::<N>::from_slice(cells, rule)),
+    };
+
+    automaton.run(cycles);
+    automaton.count()
+}
+
+// The slice where every axis after the first two is 0, drawn in a box that
+// fits all generations.
+pub fn frames<const N: usize>(cells: &[(isize, isize)], rule: Rule, cycles: usize) -> Vec<Frame> {
+    let mut life = Life::<N>::from_slice(cells, rule);
+    let mut slices: Vec<Vec<(isize, isize)>> = Vec::new();
+
+    for cycle in 0..=cycles {
+        if cycle > 0 {
+            life.step();
+        }
+        slices.push(life.active
+            .iter()
+            .filter(|p| p.0[2.min(N)..].iter().all(|&c| c == 0))
+            .map(|p| (p.0[0], if N > 1 { p.0[1] } else { 0 }))
+            .collect());
+    }
+
+    let all = slices.iter().flatten();
+    let (x0, x1) = all.clone().map(|p| p.0).fold((0, 0), |(a, b), x| (a.min(x), b.max(x)));
+    let (y0, y1) = all.map(|p| p.1).fold((0, 0), |(a, b), y| (a.min(y), b.max(y)));
+
+    slices
+        .iter()
+        .map(|slice| {
+            let mut frame = vec![vec!['.'; (x1 - x0 + 1) as usize]; (y1 - y0 + 1) as usize];
+            for &(x, y) in slice {
+                frame[(y - y0) as usize][(x - x0) as usize] = '#';
+            }
+            frame
+        })
+        .collect()
+}
+
+#[aoc_generator(day17)]
+pub fn gen(input: &str) -> Vec<(isize, isize)> {
     input
         .lines()
         .enumerate()
-        .for_each(|(y, s)| s
+        .flat_map(|(y, s)| s
             .chars()
             .enumerate()
-            .for_each(|(x, v)| {
-                if v == '#' {
-                    h.insert(Point {
-                        x: x as isize,
-                        y: y as isize,
-                        z: 0,
-                        w: None,
-                    });
-                }
-            })
-        );
-
-    h
+            .filter(|&(_, v)| v == '#')
+            .map(move |(x, _)| (x as isize, y as isize)))
+        .collect()
 }
 
-fn solve(h: &HashSet<Point>) -> usize {
-    let mut h = h.next_state();
-    for _ in 1..6 {
-        h = h.next_state();
-    }
-    h.len()
+fn solve<const N: usize>(cells: &[(isize, isize)]) -> usize {
+    simulate::<N>(cells, Rule::conway(), 6, Backend::Sparse)
 }
 
 #[aoc(day17, part1)]
-pub fn solve_part1(h: &HashSet<Point>) -> usize {
-    solve(h)
+pub fn solve_part1(cells: &[(isize, isize)]) -> usize {
+    solve::<3>(cells)
 }
 
 #[aoc(day17, part2)]
-pub fn solve_part2(h: &HashSet<Point>) -> usize {
-    let h: HashSet<Point> = h
-        .into_iter()
-        .map(|x| Point { w: Some(0), ..*x })
-        .collect();
-    solve(&h)
+pub fn solve_part2(cells: &[(isize, isize)]) -> usize {
+    solve::<4>(cells)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::animation::frame_to_string;
 
     fn get_input() -> &'static str {
         return ".#.
@@ -159,4 +429,67 @@ mod tests {
     fn test_part2() {
         assert_eq!(solve_part2(&gen(get_input())), 848);
     }
+
+    #[test]
+    fn test_dimensions() {
+        let blinker = Life::<2>::new(vec![Point([0, -1]), Point([0, 0]), Point([0, 1])], Rule::conway());
+        let mut life = blinker.clone();
+        life.step();
+        assert_eq!(life.active, [Point([-1, 0]), Point([0, 0]), Point([1, 0])].iter().copied().collect());
+        life.step();
+        assert_eq!(life.active, blinker.active);
+
+        let cells = gen(get_input());
+        for cycles in 0..3 {
+            let mut folded = Life::<5>::from_slice(&cells, Rule::conway());
+            let mut plain = folded.without_folding();
+            folded.run(cycles);
+            plain.run(cycles);
+
+            assert_eq!(folded.count(), plain.count());
+            assert_eq!(
+                folded.points().into_iter().collect::<HashSet<_>>(),
+                plain.active
+            );
+        }
+
+        let mut life = Life::<3>::from_slice(&cells, "B36/S23".parse().unwrap());
+        let mut plain = life.without_folding();
+        life.run(3);
+        plain.run(3);
+        assert_eq!(life.count(), plain.count());
+    }
+
+    #[test]
+    fn test_frames() {
+        let frames = frames::<3>(&gen(get_input()), Rule::conway(), 2);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frame_to_string(&frames[0]), "..#..
+...#.
+.###.
+.....
+.....");
+        assert_eq!(frame_to_string(&frames[1]), ".....
+.#.#.
+..##.
+..#..
+.....");
+    }
+
+    #[test]
+    fn test_dense() {
+        let cells = gen(get_input());
+        assert_eq!(simulate::<3>(&cells, Rule::conway(), 6, Backend::Dense), 112);
+        assert_eq!(simulate::<4>(&cells, Rule::conway(), 6, Backend::Dense), 848);
+        assert_eq!(simulate::<5>(&cells, Rule::conway(), 6, Backend::Dense), 5760);
+        assert_eq!(simulate::<6>(&cells, Rule::conway(), 6, Backend::Dense), 35936);
+        assert_eq!(simulate::<2>(&cells, Rule::conway(), 6, Backend::Dense),
+                   simulate::<2>(&cells, Rule::conway(), 6, Backend::Sparse));
+
+        let highlife: Rule = "B36/S23".parse().unwrap();
+        for cycles in 0..4 {
+            assert_eq!(simulate::<5>(&cells, highlife.clone(), cycles, Backend::Dense),
+                       simulate::<5>(&cells, highlife.clone(), cycles, Backend::Sparse));
+        }
+    }
 }
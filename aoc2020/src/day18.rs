@@ -1,4 +1,7 @@
-#[derive(Debug, Clone)]
+use crate::bignum::{BigInt, Rational};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Empty,
     Number(isize),
@@ -17,7 +20,7 @@ pub enum Token {
     Eos,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum AddPrecedence {
     Equal,
     Before
@@ -120,21 +123,136 @@ impl ExprParser {
             panic!("Invalid token, expected right parenthesis")
         }
         self.consume();
-        Some(e)
+        Some(Expr::Par(Box::new(e)))
+    }
+}
+
+impl AddPrecedence {
+    // Binding power and associativity of an operator node, as the parser reads it.
+    fn operator(&self, expr: &Expr) -> Option<(u8, bool)> {
+        match (self, expr) {
+            (AddPrecedence::Equal, Expr::Add(..)) | (AddPrecedence::Equal, Expr::Mul(..)) => Some((1, false)),
+            (AddPrecedence::Before, Expr::Add(..)) => Some((2, false)),
+            (AddPrecedence::Before, Expr::Mul(..)) => Some((1, true)),
+            _ => None,
+        }
     }
 }
 
 impl Expr {
-    fn from_str(s: &str, add_prec: AddPrecedence) -> Expr {
+    pub fn from_str(s: &str, add_prec: AddPrecedence) -> Expr {
         ExprParser::new(s, add_prec).parse()
     }
 
     fn evaluate(&self) -> isize {
-        fn eval(expr: &Expr) -> isize {
+        self.evaluate_as().expect("Overflow while evaluating expression")
+    }
+
+    pub fn strip_parens(&self) -> Expr {
+        match self {
+            Expr::Par(e) => e.strip_parens(),
+            Expr::Add(l, r) => Expr::Add(Box::new(l.strip_parens()), Box::new(r.strip_parens())),
+            Expr::Mul(l, r) => Expr::Mul(Box::new(l.strip_parens()), Box::new(r.strip_parens())),
+            e => e.clone(),
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Expr::Empty => "",
+            Expr::Number(_) => "",
+            Expr::Add(..) => "+",
+            Expr::Mul(..) => "*",
+            Expr::Par(..) => "()",
+        }
+    }
+
+    // Ignores the source parentheses and only adds the ones `add_prec` needs
+    // for the string to parse back to the same tree.
+    pub fn to_canonical(&self, add_prec: AddPrecedence) -> String {
+        fn child(e: &Expr, add_prec: AddPrecedence, prec: u8, paren_on_tie: bool, out: &mut String) {
+            let e = e.strip_parens();
+            let paren = match add_prec.operator(&e) {
+                Some((p, _)) => p < prec || (p == prec && paren_on_tie),
+                None => false,
+            };
+
+            if paren {
+                out.push('(');
+            }
+            canonical(&e, add_prec, out);
+            if paren {
+                out.push(')');
+            }
+        }
+
+        fn canonical(e: &Expr, add_prec: AddPrecedence, out: &mut String) {
+            match e {
+                Expr::Empty => {}
+                Expr::Number(n) => out.push_str(&n.to_string()),
+                Expr::Par(e) => canonical(e, add_prec, out),
+                Expr::Add(l, r) | Expr::Mul(l, r) => {
+                    let (prec, right_assoc) = add_prec.operator(e).unwrap();
+                    child(l, add_prec, prec, right_assoc, out);
+                    out.push(' ');
+                    out.push_str(e.symbol());
+                    out.push(' ');
+                    child(r, add_prec, prec, !right_assoc, out);
+                }
+            }
+        }
+
+        let mut out = String::new();
+        canonical(self, add_prec, &mut out);
+        out
+    }
+
+    pub fn to_sexpr(&self) -> String {
+        match self {
+            Expr::Empty => "()".to_string(),
+            Expr::Number(n) => n.to_string(),
+            Expr::Add(l, r) | Expr::Mul(l, r) =>
+                format!("({} {} {})", self.symbol(), l.to_sexpr(), r.to_sexpr()),
+            Expr::Par(e) => format!("(par {})", e.to_sexpr()),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        fn node(e: &Expr, id: &mut usize, out: &mut String) -> usize {
+            let me = *id;
+            *id += 1;
+
+            let label = match e {
+                Expr::Number(n) => n.to_string(),
+                e => e.symbol().to_string(),
+            };
+            out.push_str(&format!("    n{} [label=\"{}\"];\n", me, label));
+
+            let children: Vec<&Expr> = match e {
+                Expr::Add(l, r) | Expr::Mul(l, r) => vec![l, r],
+                Expr::Par(e) => vec![e],
+                _ => vec![],
+            };
+            for c in children {
+                let c = node(c, id, out);
+                out.push_str(&format!("    n{} -> n{};\n", me, c));
+            }
+
+            me
+        }
+
+        let mut out = String::from("digraph expr {\n");
+        node(self, &mut 0, &mut out);
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn evaluate_as<T: Value>(&self) -> Option<T> {
+        fn eval<T: Value>(expr: &Expr) -> Option<T> {
             match expr {
-                Expr::Number(n) => *n,
-                Expr::Add(left, right) => eval(left) + eval(right),
-                Expr::Mul(left, right) => eval(left) * eval(right),
+                Expr::Number(n) => T::from_number(*n),
+                Expr::Add(left, right) => eval::<T>(left)?.try_add(eval(right)?),
+                Expr::Mul(left, right) => eval::<T>(left)?.try_mul(eval(right)?),
                 Expr::Par(expr) => eval(expr),
                 _ => panic!("Can't evaluate expression")
             }
@@ -143,6 +261,83 @@ impl Expr {
     }
 }
 
+// Writes the expression back with the parentheses of the source only.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Empty => Ok(()),
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Add(l, r) | Expr::Mul(l, r) => write!(f, "{} {} {}", l, self.symbol(), r),
+            Expr::Par(e) => write!(f, "({})", e),
+        }
+    }
+}
+
+// Numeric backend for `Expr::evaluate_as`, `None` means the value overflowed.
+pub trait Value: Sized {
+    fn from_number(n: isize) -> Option<Self>;
+    fn try_add(self, other: Self) -> Option<Self>;
+    fn try_mul(self, other: Self) -> Option<Self>;
+}
+
+macro_rules! impl_value_checked {
+    ($($t:ty),*) => {
+        $(
+            impl Value for $t {
+                fn from_number(n: isize) -> Option<Self> {
+                    use std::convert::TryFrom;
+                    <$t>::try_from(n).ok()
+                }
+
+                fn try_add(self, other: Self) -> Option<Self> {
+                    self.checked_add(other)
+                }
+
+                fn try_mul(self, other: Self) -> Option<Self> {
+                    self.checked_mul(other)
+                }
+            }
+        )*
+    };
+}
+
+impl_value_checked!(isize, i64, i128);
+
+impl Value for BigInt {
+    fn from_number(n: isize) -> Option<Self> {
+        Some(BigInt::from(n as i128))
+    }
+
+    fn try_add(self, other: Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn try_mul(self, other: Self) -> Option<Self> {
+        Some(self * other)
+    }
+}
+
+impl Value for Rational {
+    fn from_number(n: isize) -> Option<Self> {
+        Some(Rational::from(n as i128))
+    }
+
+    fn try_add(self, other: Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn try_mul(self, other: Self) -> Option<Self> {
+        Some(self * other)
+    }
+}
+
+pub fn sum_lines<T: Value>(input: &str, add_prec: AddPrecedence) -> Option<T> {
+    input
+        .lines()
+        .map(|line| Expr::from_str(line, add_prec).evaluate_as::<T>())
+        .try_fold(T::from_number(0)?, |acc, n| acc.try_add(n?))
+}
+
 #[aoc(day18, part1)]
 pub fn solve_part1(input: &str) -> isize {
     input
@@ -189,4 +384,74 @@ mod tests {
         assert_eq!(Expr::from_str(s, AddPrecedence::Equal).evaluate(), 13632);
         assert_eq!(Expr::from_str(s, AddPrecedence::Before).evaluate(), 23340);
     }
+
+    #[test]
+    fn test_backends() {
+        let s = "5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))";
+        let e = Expr::from_str(s, AddPrecedence::Before);
+        assert_eq!(e.evaluate_as::<i64>(), Some(669060));
+        assert_eq!(e.evaluate_as::<i128>(), Some(669060));
+        assert_eq!(e.evaluate_as::<BigInt>(), Some(BigInt::from(669060)));
+        assert_eq!(e.evaluate_as::<Rational>(), Some(Rational::from(669060)));
+
+        let s = "999999999 * 999999999 * (999999999 * 999999999 + 1) * 999999999";
+        let e = Expr::from_str(s, AddPrecedence::Equal);
+        assert_eq!(e.evaluate_as::<i64>(), None);
+        assert_eq!(e.evaluate_as::<i128>(), None);
+        assert_eq!(
+            e.evaluate_as::<BigInt>().unwrap().to_string(),
+            "999999995000000010999999987000000007999999998"
+        );
+
+        let input = "2 * 3 + (4 * 5)\n1 + (2 * 3) + (4 * (5 + 6))";
+        assert_eq!(sum_lines::<i64>(input, AddPrecedence::Before), Some(46 + 51));
+    }
+
+    #[test]
+    fn test_print() {
+        let s = "5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))";
+        let e = Expr::from_str(s, AddPrecedence::Before);
+        assert_eq!(e.to_string(), s);
+        assert_eq!(e.to_canonical(AddPrecedence::Before), "5 * 9 * 7 * 3 * 3 + 9 * 3 + (8 + 6 * 4)");
+        assert_eq!(e.to_canonical(AddPrecedence::Equal), "5 * (9 * (7 * (3 * (3 + 9 * (3 + (8 + 6 * 4))))))");
+        assert_eq!(e.to_sexpr(), "(* 5 (* 9 (par (* 7 (* 3 (* (+ 3 9) (+ 3 (par (* (+ 8 6) 4)))))))))");
+
+        let e = Expr::from_str(s, AddPrecedence::Equal);
+        assert_eq!(e.to_canonical(AddPrecedence::Equal), "5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))");
+        assert_eq!(e.to_canonical(AddPrecedence::Before), "(5 * 9) * (((7 * 3) * 3) + 9 * 3) + (8 + 6 * 4)");
+
+        let dot = Expr::from_str("1 + 2 * 3", AddPrecedence::Before).to_dot();
+        assert_eq!(dot, "digraph expr {
+    n0 [label=\"*\"];
+    n1 [label=\"+\"];
+    n2 [label=\"1\"];
+    n1 -> n2;
+    n3 [label=\"2\"];
+    n1 -> n3;
+    n0 -> n1;
+    n4 [label=\"3\"];
+    n0 -> n4;
+}
+");
+    }
+
+    #[test]
+    fn test_canonical_roundtrip() {
+        let lines = [
+            "1 + 2 * 3 + 4 * 5 + 6",
+            "1 + (2 * 3) + (4 * (5 + 6))",
+            "5 + (8 * 3 + 9 + 3 * 4 * 3)",
+            "((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2",
+        ];
+
+        for s in lines.iter() {
+            for &p in [AddPrecedence::Equal, AddPrecedence::Before].iter() {
+                let e = Expr::from_str(s, p).strip_parens();
+                for &q in [AddPrecedence::Equal, AddPrecedence::Before].iter() {
+                    let printed = e.to_canonical(q);
+                    assert_eq!(Expr::from_str(&printed, q).strip_parens(), e);
+                }
+            }
+        }
+    }
 }
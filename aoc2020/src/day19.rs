@@ -6,9 +6,11 @@ use nom::multi::{many1, many0};
 use nom::combinator::{map_res, opt, recognize};
 use itertools::Itertools;
 use nom::branch::alt;
-use nom::lib::std::collections::HashMap;
+use nom::lib::std::collections::{HashMap, HashSet, BTreeSet};
+use regex::{Regex, RegexBuilder};
+use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Letter(char),
     Numbers(Vec<Vec<usize>>),
@@ -70,36 +72,440 @@ fn parse(s: &str) -> HashMap<usize, Expr> {
         .collect()
 }
 
-fn validate_one<'a>(h: &HashMap<usize, Expr>, s: &'a str, expr: &Vec<usize>) -> Option<&'a str> {
-    if s.is_empty() {
-        return None;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    rule: usize,
+    alt: usize,
+    dot: usize,
+    origin: usize,
+}
+
+fn get_rule(h: &HashMap<usize, Expr>, n: usize) -> &Expr {
+    h.get(&n).unwrap_or_else(|| panic!("Unknown rule {}", n))
+}
+
+// Earley recognizer, rules never derive the empty string so there is no
+// nullable completion to take care of.
+pub fn matches(h: &HashMap<usize, Expr>, start: usize, s: &str) -> bool {
+    let chars = s.chars().collect_vec();
+    let mut chart: Vec<Vec<Item>> = vec![Vec::new(); chars.len() + 1];
+    let mut seen: Vec<HashSet<Item>> = vec![HashSet::new(); chars.len() + 1];
+
+    fn add(chart: &mut [Vec<Item>], seen: &mut [HashSet<Item>], k: usize, item: Item) {
+        if seen[k].insert(item) {
+            chart[k].push(item);
+        }
+    }
+
+    let start_alts = match get_rule(h, start) {
+        Expr::Letter(c) => return chars.len() == 1 && chars[0] == *c,
+        Expr::Numbers(alts) => alts.len(),
+    };
+    for alt in 0..start_alts {
+        add(&mut chart, &mut seen, 0, Item { rule: start, alt, dot: 0, origin: 0 });
     }
 
-    let mut s = s;
-    for num in expr {
-        s = validate(h, s, &h[num])?;
+    for k in 0..=chars.len() {
+        let mut i = 0;
+        while i < chart[k].len() {
+            let item = chart[k][i];
+            i += 1;
+
+            let alt = match get_rule(h, item.rule) {
+                Expr::Numbers(alts) => &alts[item.alt],
+                Expr::Letter(_) => unreachable!(),
+            };
+
+            match alt.get(item.dot) {
+                None => {
+                    let waiting = chart[item.origin].clone();
+                    for parent in waiting {
+                        if let Expr::Numbers(alts) = get_rule(h, parent.rule) {
+                            if alts[parent.alt].get(parent.dot) == Some(&item.rule) {
+                                add(&mut chart, &mut seen, k, Item { dot: parent.dot + 1, ..parent });
+                            }
+                        }
+                    }
+                }
+                Some(&next) => match get_rule(h, next) {
+                    Expr::Letter(c) => {
+                        if chars.get(k) == Some(c) {
+                            add(&mut chart, &mut seen, k + 1, Item { dot: item.dot + 1, ..item });
+                        }
+                    }
+                    Expr::Numbers(alts) => {
+                        for alt in 0..alts.len() {
+                            add(&mut chart, &mut seen, k, Item { rule: next, alt, dot: 0, origin: k });
+                        }
+                    }
+                },
+            }
+        }
     }
 
-    Some(s)
+    chart[chars.len()]
+        .iter()
+        .any(|item| item.rule == start && item.origin == 0 && match get_rule(h, start) {
+            Expr::Numbers(alts) => item.dot == alts[item.alt].len(),
+            Expr::Letter(_) => false,
+        })
+}
+
+fn validate_msg(h: &HashMap<usize, Expr>, s: &str) -> bool {
+    matches(h, 0, s)
+}
+
+// Replaces (or adds) rules with the ones written in `overrides`, using the
+// same syntax as the puzzle input.
+pub fn apply_overrides(h: &mut HashMap<usize, Expr>, overrides: &str) {
+    h.extend(parse(overrides));
 }
 
-fn validate<'a>(h: &HashMap<usize, Expr>, s: &'a str, expr: &Expr) -> Option<&'a str> {
+#[derive(Debug, PartialEq)]
+pub enum CompileError {
+    UnknownRule(usize),
+    NotRegular(Vec<usize>),
+    Regex(String),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::UnknownRule(n) => write!(f, "rule {} is referenced but never defined", n),
+            CompileError::NotRegular(rules) => write!(
+                f,
+                "rule set is recursive, can't compile it to a regex (recursive rules: {})",
+                rules.iter().join(", ")
+            ),
+            CompileError::Regex(e) => write!(f, "generated pattern was rejected: {}", e),
+        }
+    }
+}
+
+fn referenced(expr: &Expr) -> Vec<usize> {
     match expr {
-        Expr::Letter(c) if s.starts_with(*c) => Some(&s[1..]),
-        Expr::Numbers(nums) => nums
-            .iter()
-            .find_map(|expr| validate_one(h, s, expr)),
-        _ => None
+        Expr::Letter(_) => Vec::new(),
+        Expr::Numbers(alts) => alts.iter().flatten().copied().unique().collect(),
     }
 }
 
-fn validate_msg(h: &HashMap<usize, Expr>, s: &str) -> bool {
-    match validate(h, s, &h[&0]) {
-        Some(s) if s.is_empty() => true,
-        _ => false
+// Rules taking part in a cycle (Tarjan's strongly connected components).
+pub fn recursive_rules(h: &HashMap<usize, Expr>) -> Vec<usize> {
+    struct State {
+        index: HashMap<usize, usize>,
+        low: HashMap<usize, usize>,
+        stack: Vec<usize>,
+        on_stack: HashSet<usize>,
+        ret: Vec<usize>,
+    }
+
+    fn visit(h: &HashMap<usize, Expr>, n: usize, st: &mut State) {
+        let i = st.index.len();
+        st.index.insert(n, i);
+        st.low.insert(n, i);
+        st.stack.push(n);
+        st.on_stack.insert(n);
+
+        let next = h.get(&n).map(referenced).unwrap_or_default();
+        for &m in next.iter() {
+            if !h.contains_key(&m) {
+                continue;
+            }
+            if !st.index.contains_key(&m) {
+                visit(h, m, st);
+                let low = st.low[&n].min(st.low[&m]);
+                st.low.insert(n, low);
+            } else if st.on_stack.contains(&m) {
+                let low = st.low[&n].min(st.index[&m]);
+                st.low.insert(n, low);
+            }
+        }
+
+        if st.low[&n] == st.index[&n] {
+            let mut component = Vec::new();
+            loop {
+                let m = st.stack.pop().unwrap();
+                st.on_stack.remove(&m);
+                component.push(m);
+                if m == n {
+                    break;
+                }
+            }
+            if component.len() > 1 || next.contains(&n) {
+                st.ret.append(&mut component);
+            }
+        }
+    }
+
+    let mut st = State {
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: HashSet::new(),
+        ret: Vec::new(),
+    };
+    for &n in h.keys().sorted() {
+        if !st.index.contains_key(&n) {
+            visit(h, n, &mut st);
+        }
     }
+
+    st.ret.sort_unstable();
+    st.ret
+}
+
+fn reachable(h: &HashMap<usize, Expr>, start: usize) -> Result<HashSet<usize>, CompileError> {
+    let mut seen = HashSet::new();
+    let mut todo = vec![start];
+
+    while let Some(n) = todo.pop() {
+        if seen.insert(n) {
+            let expr = h.get(&n).ok_or(CompileError::UnknownRule(n))?;
+            todo.extend(referenced(expr));
+        }
+    }
+
+    Ok(seen)
+}
+
+pub fn compile_pattern(h: &HashMap<usize, Expr>, start: usize) -> Result<String, CompileError> {
+    let used = reachable(h, start)?;
+    let recursive = recursive_rules(h)
+        .into_iter()
+        .filter(|n| used.contains(n))
+        .collect_vec();
+    if !recursive.is_empty() {
+        return Err(CompileError::NotRegular(recursive));
+    }
+
+    fn pattern(h: &HashMap<usize, Expr>, n: usize, memo: &mut HashMap<usize, String>) -> String {
+        if let Some(p) = memo.get(&n) {
+            return p.clone();
+        }
+
+        let p = match &h[&n] {
+            Expr::Letter(c) => regex::escape(&c.to_string()),
+            Expr::Numbers(alts) => {
+                let alts = alts
+                    .iter()
+                    .map(|alt| alt.iter().map(|&m| pattern(h, m, memo)).join(""))
+                    .collect_vec();
+                if alts.len() == 1 {
+                    alts.into_iter().next().unwrap()
+                } else {
+                    format!("(?:{})", alts.join("|"))
+                }
+            }
+        };
+
+        memo.insert(n, p.clone());
+        p
+    }
+
+    Ok(format!("^{}$", pattern(h, start, &mut HashMap::new())))
+}
+
+// A non-recursive rule set compiled down to one anchored regex, which the
+// regex crate matches in linear time.
+#[derive(Debug)]
+pub struct CompiledRules {
+    pattern: String,
+    regex: Regex,
 }
 
+impl CompiledRules {
+    pub fn new(h: &HashMap<usize, Expr>, start: usize) -> Result<CompiledRules, CompileError> {
+        let pattern = compile_pattern(h, start)?;
+        let regex = RegexBuilder::new(&pattern)
+            .size_limit(1 << 30)
+            .dfa_size_limit(1 << 30)
+            .build()
+            .map_err(|e| CompileError::Regex(e.to_string()))?;
+
+        Ok(CompiledRules { pattern, regex })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        self.regex.is_match(s)
+    }
+}
+
+// Lengths of the strings each rule derives, up to `max_len`.
+pub fn rule_lengths(h: &HashMap<usize, Expr>, max_len: usize) -> HashMap<usize, BTreeSet<usize>> {
+    let mut lengths: HashMap<usize, BTreeSet<usize>> = h
+        .keys()
+        .map(|&n| (n, BTreeSet::new()))
+        .collect();
+
+    loop {
+        let mut changed = false;
+
+        for (&n, expr) in h.iter() {
+            let new = match expr {
+                Expr::Letter(_) => [1].iter().copied().filter(|&l| l <= max_len).collect(),
+                Expr::Numbers(alts) => alts
+                    .iter()
+                    .flat_map(|alt| alt.iter().fold(
+                        [0].iter().copied().collect::<BTreeSet<usize>>(),
+                        |acc, m| acc
+                            .iter()
+                            .cartesian_product(lengths.get(m).unwrap_or_else(|| panic!("Unknown rule {}", m)))
+                            .map(|(a, b)| a + b)
+                            .filter(|&l| l <= max_len)
+                            .collect(),
+                    ))
+                    .collect(),
+            };
+
+            if new != lengths[&n] {
+                lengths.insert(n, new);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return lengths;
+        }
+    }
+}
+
+// Every string of at most `max_len` characters derived by `rule`.
+pub fn generate(h: &HashMap<usize, Expr>, rule: usize, max_len: usize) -> BTreeSet<String> {
+    let used = reachable(h, rule).unwrap_or_else(|e| panic!("{}", e));
+    let mut langs: HashMap<usize, BTreeSet<String>> = used
+        .iter()
+        .map(|&n| (n, BTreeSet::new()))
+        .collect();
+
+    loop {
+        let mut changed = false;
+
+        for &n in used.iter() {
+            let new: BTreeSet<String> = match &h[&n] {
+                Expr::Letter(c) if max_len > 0 => [c.to_string()].iter().cloned().collect(),
+                Expr::Letter(_) => BTreeSet::new(),
+                Expr::Numbers(alts) => alts
+                    .iter()
+                    .flat_map(|alt| alt.iter().fold(
+                        [String::new()].iter().cloned().collect::<BTreeSet<String>>(),
+                        |acc, m| acc
+                            .iter()
+                            .cartesian_product(langs[m].iter())
+                            .filter(|(a, b)| a.len() + b.len() <= max_len)
+                            .map(|(a, b)| format!("{}{}", a, b))
+                            .collect(),
+                    ))
+                    .collect(),
+            };
+
+            if new != langs[&n] {
+                langs.insert(n, new);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return langs.remove(&rule).unwrap();
+        }
+    }
+}
+
+// xorshift64*, enough to pick alternatives.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn pick<T: Copy>(&mut self, v: &[T]) -> Option<T> {
+        if v.is_empty() {
+            None
+        } else {
+            Some(v[(self.next() % v.len() as u64) as usize])
+        }
+    }
+}
+
+// Draws random derivations by first choosing a reachable length, then
+// splitting it between the parts of an alternative.
+pub struct Sampler<'a> {
+    h: &'a HashMap<usize, Expr>,
+    lengths: HashMap<usize, BTreeSet<usize>>,
+    rng: Rng,
+}
+
+impl<'a> Sampler<'a> {
+    pub fn new(h: &'a HashMap<usize, Expr>, max_len: usize, seed: u64) -> Sampler<'a> {
+        Sampler {
+            h,
+            lengths: rule_lengths(h, max_len),
+            rng: Rng(seed | 1),
+        }
+    }
+
+    pub fn sample(&mut self, rule: usize) -> Option<String> {
+        let lengths = self.lengths.get(&rule)?.iter().copied().collect_vec();
+        let len = self.rng.pick(&lengths)?;
+        let mut s = String::new();
+        self.sample_len(rule, len, &mut s);
+        Some(s)
+    }
+
+    fn fits(&self, parts: &[usize], len: usize) -> bool {
+        match parts.split_first() {
+            None => len == 0,
+            Some((first, rest)) => self.lengths[first]
+                .range(..=len)
+                .any(|&l| self.fits(rest, len - l)),
+        }
+    }
+
+    fn sample_len(&mut self, rule: usize, len: usize, out: &mut String) {
+        let alts = match &self.h[&rule] {
+            Expr::Letter(c) => return out.push(*c),
+            Expr::Numbers(alts) => alts,
+        };
+
+        let alts = alts.iter().filter(|alt| self.fits(alt, len)).collect_vec();
+        let alt = (*self.rng.pick(&alts).unwrap()).clone();
+
+        let mut len = len;
+        for (i, part) in alt.iter().enumerate() {
+            let choices = self.lengths[part]
+                .range(..=len)
+                .copied()
+                .filter(|&l| self.fits(&alt[i + 1..], len - l))
+                .collect_vec();
+            let l = self.rng.pick(&choices).unwrap();
+            self.sample_len(*part, l, out);
+            len -= l;
+        }
+    }
+}
+
+// Only recursive rule sets go to the general matcher, the other errors are
+// passed up.
+fn count_valid(h: &HashMap<usize, Expr>, msgs: &[String]) -> Result<usize, CompileError> {
+    match CompiledRules::new(h, 0) {
+        Ok(rules) => Ok(msgs.iter().filter(|s| rules.is_match(s)).count()),
+        // Recursive, or too big for the regex crate.
+        Err(CompileError::NotRegular(_)) | Err(CompileError::Regex(_)) =>
+            Ok(msgs.iter().filter(|s| validate_msg(h, s)).count()),
+        Err(e) => Err(e),
+    }
+}
+
+const PART2_OVERRIDES: &str = "8: 42 | 42 8
+11: 42 31 | 42 11 31";
+
 #[aoc_generator(day19)]
 pub fn gen(input: &str) -> (HashMap<usize, Expr>, Vec<String>) {
     let mut sp = input.split("\n\n");
@@ -111,33 +517,14 @@ pub fn gen(input: &str) -> (HashMap<usize, Expr>, Vec<String>) {
 
 #[aoc(day19, part1)]
 pub fn solve_part1((h, msgs): &(HashMap<usize, Expr>, Vec<String>)) -> usize {
-    msgs
-        .iter()
-        .filter(|s| validate_msg(h, &s[..]))
-        .count()
-}
-
-fn part2(h: &HashMap<usize, Expr>, s: &str) -> bool {
-    let mut s = s;
-    let nums = vec![42, 31];
-    let mut ret = vec![0, 0];
-
-    for (i, n) in nums.iter().enumerate() {
-        while let Some(t) = validate(h, s, &h[n]) {
-            ret[i] += 1;
-            s = t;
-        }
-    }
-
-    s.is_empty() && ret[1] > 0 && ret[0] > ret[1]
+    count_valid(h, msgs).unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[aoc(day19, part2)]
 pub fn solve_part2((h, msgs): &(HashMap<usize, Expr>, Vec<String>)) -> usize {
-    msgs
-        .iter()
-        .filter(|s| part2(h, &s[..]))
-        .count()
+    let mut h = h.clone();
+    apply_overrides(&mut h, PART2_OVERRIDES);
+    count_valid(&h, msgs).unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[cfg(test)]
@@ -236,4 +623,83 @@ aabbbbbaabbbaaaaaabbbbbababaaaaabbaaabba";
     fn test_part2() {
         assert_eq!(solve_part2(&gen(get_input2())), 12);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_matches() {
+        let h = parse("0: 1 4
+1: 4 | 4 4
+4: \"a\"");
+        assert!(matches(&h, 0, "aa"));
+        assert!(matches(&h, 0, "aaa"));
+        assert!(!matches(&h, 0, "aaaa"));
+        assert!(matches(&h, 4, "a"));
+
+        let mut h = parse("0: 8 11
+8: 42
+11: 42 31
+42: \"a\"
+31: \"b\"");
+        assert!(!matches(&h, 0, "aaabb"));
+        apply_overrides(&mut h, PART2_OVERRIDES);
+        assert!(matches(&h, 0, "aaabb"));
+        assert!(matches(&h, 0, "aab"));
+        assert!(!matches(&h, 0, "abb"));
+        assert!(!matches(&h, 0, "aaba"));
+    }
+
+    #[test]
+    fn test_compile() {
+        let (h, _) = gen(get_input());
+        assert_eq!(recursive_rules(&h), Vec::<usize>::new());
+        let rules = CompiledRules::new(&h, 0).unwrap();
+        assert_eq!(rules.pattern(), "^a(?:(?:aa|bb)(?:ab|ba)|(?:ab|ba)(?:aa|bb))b$");
+        assert!(rules.is_match("ababbb"));
+        assert!(rules.is_match("abbbab"));
+        assert!(!rules.is_match("bababa"));
+        assert!(!rules.is_match("aaaabbb"));
+
+        let (mut h, _) = gen(get_input2());
+        assert!(CompiledRules::new(&h, 0).is_ok());
+        apply_overrides(&mut h, PART2_OVERRIDES);
+        assert_eq!(recursive_rules(&h), vec![8, 11]);
+        assert_eq!(CompiledRules::new(&h, 0).unwrap_err(), CompileError::NotRegular(vec![8, 11]));
+        assert!(CompiledRules::new(&h, 42).is_ok());
+
+        let h = parse("0: 1 2\n1: \"a\"");
+        assert_eq!(compile_pattern(&h, 0), Err(CompileError::UnknownRule(2)));
+        assert_eq!(count_valid(&h, &["a".to_string()]), Err(CompileError::UnknownRule(2)));
+
+        // Each rule is a group in the next one, nested deeper than the regex
+        // crate allows.
+        let text = (0..300).map(|n| format!("{}: {} | 300", n, n + 1)).join("\n");
+        let h = parse(&format!("{}\n300: \"a\"", text));
+        assert!(matches!(CompiledRules::new(&h, 0), Err(CompileError::Regex(_))));
+        assert_eq!(count_valid(&h, &["a".to_string(), "aa".to_string()]), Ok(1));
+    }
+
+    #[test]
+    fn test_generate() {
+        let (h, _) = gen(get_input());
+        let all = generate(&h, 0, 10);
+        assert_eq!(all.len(), 8);
+        assert!(all.contains("ababbb"));
+        assert!(all.contains("abbbab"));
+        assert!(all.iter().all(|s| matches(&h, 0, s)));
+        assert_eq!(generate(&h, 0, 5).len(), 0);
+
+        let (mut h, _) = gen(get_input2());
+        apply_overrides(&mut h, PART2_OVERRIDES);
+        let lengths = rule_lengths(&h, 40);
+        assert_eq!(lengths[&42], [5].iter().copied().collect());
+        assert_eq!(lengths[&31], [5].iter().copied().collect());
+        assert_eq!(lengths[&0], [15, 20, 25, 30, 35, 40].iter().copied().collect());
+
+        let short = generate(&h, 0, 15);
+        assert!(short.iter().all(|s| s.len() == 15 && matches(&h, 0, s)));
+
+        let mut sampler = Sampler::new(&h, 40, 2020);
+        let msgs = (0..20).map(|_| sampler.sample(0).unwrap()).collect_vec();
+        assert!(msgs.iter().all(|s| matches(&h, 0, s)));
+        assert_eq!(solve_part2(&(parse(get_input2()), msgs)), 20);
+    }
+}
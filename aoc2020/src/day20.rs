@@ -1,5 +1,12 @@
 use core::fmt;
 use nom::lib::std::fmt::Formatter;
+use std::str::FromStr;
+use nom::lib::std::collections::{HashMap, HashSet};
+use itertools::Itertools;
+use crate::raster::Raster;
+use crate::dihedral::{D4, View};
+use std::{fs, io};
+use std::path::Path;
 
 #[derive(Clone)]
 pub struct Tile {
@@ -20,263 +27,318 @@ impl fmt::Debug for Tile {
     }
 }
 
-impl Tile {
-    fn from_str(s: &str) -> Tile {
-        let mut lines = s.lines();
-        let num = lines.next().unwrap()[5..9].parse::<usize>().unwrap();
-        let grid = lines.map(|s| s.chars().collect()).collect();
+#[derive(Debug, PartialEq)]
+pub enum TileError {
+    MissingHeader,
+    BadHeader(String),
+    Empty(usize),
+    Ragged(usize),
+    SizeMismatch { num: usize, size: (usize, usize), expected: (usize, usize) },
+}
 
-        Tile {
-            num,
-            grid,
+impl fmt::Display for TileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TileError::MissingHeader => write!(f, "missing tile header"),
+            TileError::BadHeader(s) => write!(f, "malformed tile header: {:?}", s),
+            TileError::Empty(num) => write!(f, "tile {} has no content", num),
+            TileError::Ragged(num) => write!(f, "tile {} has lines of different lengths", num),
+            TileError::SizeMismatch { num, size, expected } => write!(
+                f,
+                "tile {} is {}x{}, expected {}x{}",
+                num, size.0, size.1, expected.0, expected.1
+            ),
         }
     }
+}
 
-    fn rotate(&mut self) {
-        let grid = self.grid.clone();
-        let len = grid.len();
+impl FromStr for Tile {
+    type Err = TileError;
 
-        for i in 0..len {
-            for j in 0..len {
-                self.grid[len - i - 1][j] = grid[j][i];
-            }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        let header = lines.next().ok_or(TileError::MissingHeader)?.trim();
+        let num = header
+            .strip_prefix("Tile ")
+            .and_then(|s| s.strip_suffix(':'))
+            .and_then(|s| s.trim().parse::<usize>().ok())
+            .ok_or_else(|| TileError::BadHeader(header.to_string()))?;
+
+        let grid: Vec<Vec<char>> = lines.map(|s| s.trim_end().chars().collect()).collect();
+        if grid.is_empty() || grid[0].is_empty() {
+            return Err(TileError::Empty(num));
+        }
+        if grid.iter().any(|l| l.len() != grid[0].len()) {
+            return Err(TileError::Ragged(num));
         }
-    }
 
-    fn flip(&mut self) {
-        let grid = self.grid.clone();
-        let len = grid.len();
+        Ok(Tile {
+            num,
+            grid,
+        })
+    }
+}
 
-        for i in 0..len {
-            for j in 0..len {
-                self.grid[len - j - 1][i] = grid[j][i];
-            }
-        }
+impl Tile {
+    pub fn width(&self) -> usize {
+        self.grid.first().map_or(0, |l| l.len())
     }
 
-    fn get_top_edge(&self) -> Vec<char> {
-        let mut v = Vec::new();
-        let len = self.grid.len();
+    pub fn height(&self) -> usize {
+        self.grid.len()
+    }
 
-        for i in 0..len {
-            v.push(self.grid[0][i]);
-        }
+    // The tile as seen after `t`, nothing is copied.
+    pub fn view(&self, t: D4) -> View<'_, char> {
+        t.view(&self.grid)
+    }
 
-        v
+    // Top and bottom are read left to right, left and right top to bottom,
+    // as seen after `t`.
+    fn edge_codes(&self, t: D4) -> [EdgeCode; 4] {
+        let view = self.view(t);
+        let (w, h) = (view.width(), view.height());
+        [
+            edge_code(view.row(0).copied()),
+            edge_code(view.col(w - 1).copied()),
+            edge_code(view.row(h - 1).copied()),
+            edge_code(view.col(0).copied()),
+        ]
     }
 
-    fn get_bottom_edge(&self) -> Vec<char> {
-        let mut v = Vec::new();
-        let len = self.grid.len();
+    fn count_sharp(&self) -> usize {
+        self
+            .grid
+            .iter()
+            .flatten()
+            .filter(|&c| *c == '#')
+            .count()
+    }
+}
 
-        for i in 0..len {
-            v.push(self.grid[len - 1][i]);
-        }
+const TOP: usize = 0;
+const RIGHT: usize = 1;
+const BOTTOM: usize = 2;
+const LEFT: usize = 3;
+
+// Edges up to 63 cells are one bit per cell behind a leading 1, so edges of
+// different lengths never collide. Longer ones keep their cells.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EdgeCode {
+    Bits(u64),
+    Cells(Vec<bool>),
+}
 
-        v
+fn edge_code(edge: impl Iterator<Item = char>) -> EdgeCode {
+    let cells = edge.map(|c| c == '#').collect_vec();
+    if cells.len() < 64 {
+        EdgeCode::Bits(cells.iter().fold(1, |acc, &c| (acc << 1) | c as u64))
+    } else {
+        EdgeCode::Cells(cells)
     }
+}
 
-    fn get_left_edge(&self) -> Vec<char> {
-        let mut v = Vec::new();
-        let len = self.grid.len();
-
-        for i in 0..len {
-            v.push(self.grid[i][0]);
+fn reverse_code(code: &EdgeCode) -> EdgeCode {
+    match code {
+        EdgeCode::Bits(bits) => {
+            let len = 63 - bits.leading_zeros();
+            EdgeCode::Bits((0..len).fold(1, |acc, i| (acc << 1) | ((bits >> i) & 1)))
         }
-
-        v
+        EdgeCode::Cells(cells) => EdgeCode::Cells(cells.iter().rev().copied().collect()),
     }
+}
 
+fn canonical(code: &EdgeCode) -> EdgeCode {
+    code.clone().min(reverse_code(code))
+}
 
-    fn get_right_edge(&self) -> Vec<char> {
-        let mut v = Vec::new();
-        let len = self.grid.len();
+// Tiles (by position in the input) sharing each canonical edge.
+pub struct EdgeIndex {
+    edges: HashMap<EdgeCode, Vec<usize>>,
+    codes: Vec<[EdgeCode; 4]>,
+}
 
-        for i in 0..len {
-            v.push(self.grid[i][len - 1]);
-        }
+impl EdgeIndex {
+    pub fn new(tiles: &[Tile]) -> EdgeIndex {
+        let codes = tiles.iter().map(|t| t.edge_codes(D4::IDENTITY)).collect_vec();
+        let mut edges: HashMap<EdgeCode, Vec<usize>> = HashMap::new();
 
-        v
-    }
+        for (i, c) in codes.iter().enumerate() {
+            for code in c.iter() {
+                let v = edges.entry(canonical(code)).or_default();
+                if !v.contains(&i) {
+                    v.push(i);
+                }
+            }
+        }
 
-    fn get_edges(&self) -> Vec<Vec<char>> {
-        vec![
-            self.get_right_edge(),
-            self.get_bottom_edge(),
-            self.get_left_edge(),
-            self.get_top_edge(),
-            self.get_right_edge().into_iter().rev().collect(),
-            self.get_bottom_edge().into_iter().rev().collect(),
-            self.get_left_edge().into_iter().rev().collect(),
-            self.get_top_edge().into_iter().rev().collect(),
-        ]
+        EdgeIndex { edges, codes }
     }
 
-    fn is_adjacent(&self, tile: &Tile) -> bool {
-        let edges = tile.get_edges();
-        self
-            .get_edges()
-            .iter()
-            .any(|e| edges
-                .iter()
-                .any(|f| e == f))
+    pub fn tiles_with_edge(&self, code: &EdgeCode) -> &[usize] {
+        self.edges.get(&canonical(code)).map_or(&[], |v| &v[..])
     }
 
-    fn is_right(&self, tile: &Tile) -> bool {
-        self.get_right_edge() == tile.get_left_edge()
+    fn is_border(&self, tile: usize, code: &EdgeCode) -> bool {
+        self.tiles_with_edge(code).iter().all(|&t| t == tile)
     }
 
-    // fn is_left(&self, tile: &Tile) -> bool {
-    //     self.get_left_edge() == tile.get_right_edge()
-    // }
-    //
-    // fn is_up(&self, tile: &Tile) -> bool {
-    //     self.get_top_edge() == tile.get_bottom_edge()
-    // }
-
-    fn is_down(&self, tile: &Tile) -> bool {
-        self.get_bottom_edge() == tile.get_top_edge()
-    }
-
-    fn edge_present(&self, edge: &Vec<char>, tiles: &Vec<Tile>) -> bool {
-        tiles
+    pub fn border_count(&self, tile: usize) -> usize {
+        self.codes[tile]
             .iter()
-            .filter(|t| t.num != self.num)
-            .any(|t| {
-                t.get_edges()
-                    .iter()
-                    .any(|e| *e == *edge)
-            })
-    }
-
-    fn right_present(&self, tiles: &Vec<Tile>) -> bool {
-        let edge = self.get_right_edge();
-        self.edge_present(&edge, tiles)
+            .filter(|code| self.is_border(tile, code))
+            .count()
     }
 
-    // fn left_present(&self, tiles: &Vec<Tile>) -> bool {
-    //     let edge = self.get_left_edge();
-    //     self.edge_present(&edge, tiles)
-    // }
-    //
-    // fn up_present(&self, tiles: &Vec<Tile>) -> bool {
-    //     let edge = self.get_top_edge();
-    //     self.edge_present(&edge, tiles)
-    // }
-
-    fn down_present(&self, tiles: &Vec<Tile>) -> bool {
-        let edge = self.get_bottom_edge();
-        self.edge_present(&edge, tiles)
+    pub fn neighbours(&self, tile: usize) -> Vec<usize> {
+        self.codes[tile]
+            .iter()
+            .flat_map(|code| self.tiles_with_edge(code).iter().copied())
+            .filter(|&t| t != tile)
+            .unique()
+            .collect()
     }
 
-    fn count_sharp(&self) -> usize {
-        self
-            .grid
-            .iter()
-            .flatten()
-            .filter(|&c| *c == '#')
-            .count()
+    pub fn corners(&self) -> Vec<usize> {
+        (0..self.codes.len())
+            .filter(|&t| self.border_count(t) == 2)
+            .collect()
     }
 }
 
-fn count_adjacent(tile: &Tile, tiles: &Vec<Tile>) -> usize {
-    tiles
-        .iter()
-        .filter(|&t| t.num != tile.num && tile.is_adjacent(t))
-        .count()
+// Fills a rows x cols grid in reading order, trying only the tiles the
+// index gives for the edge on the left (or above), and backtracks when a
+// shared edge led to a dead end.
+struct Assembler<'a> {
+    index: &'a EdgeIndex,
+    oriented: Vec<Vec<[EdgeCode; 4]>>,
+    rows: usize,
+    cols: usize,
+    placed: Vec<(usize, D4)>,
+    used: Vec<bool>,
 }
 
-fn get_top_left(tiles: &mut Vec<Tile>) -> Tile {
-    let mut top_left = tiles
-        .iter()
-        .find(|&t| count_adjacent(t, &tiles) == 2)
-        .unwrap()
-        .clone();
-
-    tiles.retain(|t| t.num != top_left.num);
-
-    while !(top_left.right_present(&tiles) && top_left.down_present(&tiles)) {
-        top_left.rotate();
+impl<'a> Assembler<'a> {
+    fn edge(&self, pos: usize, side: usize) -> EdgeCode {
+        let (t, o) = self.placed[pos];
+        self.oriented[t][o.index()][side].clone()
     }
 
-    top_left
-}
+    fn solve(&mut self) -> bool {
+        let pos = self.placed.len();
+        if pos == self.rows * self.cols {
+            return true;
+        }
 
-#[derive(PartialEq)]
-enum Dir {
-    Right,
-    Down
-}
+        let col = pos % self.cols;
+        let left = if col > 0 { Some(self.edge(pos - 1, RIGHT)) } else { None };
+        let up = if pos >= self.cols { Some(self.edge(pos - self.cols, BOTTOM)) } else { None };
 
-fn get_tile_from_dir(tile: &Tile, tiles: &mut Vec<Tile>, dir: Dir) -> Option<Tile> {
-    let mut ret = None;
+        let candidates = match left.as_ref().or(up.as_ref()) {
+            Some(code) => self.index.tiles_with_edge(code).to_vec(),
+            None => (0..self.used.len())
+                .sorted_by_key(|&t| std::cmp::Reverse(self.index.border_count(t)))
+                .collect(),
+        };
+
+        for t in candidates {
+            if self.used[t] {
+                continue;
+            }
 
-    'outer: for t in tiles.iter_mut() {
-        for _ in 0..2 {
-            for _ in 0..4 {
-                if (dir == Dir::Right && tile.is_right(t)) ||
-                    (dir == Dir::Down && tile.is_down(t)) {
-                    ret = Some(t.clone());
-                    break 'outer;
+            for &o in D4::all().iter() {
+                let codes = &self.oriented[t][o.index()];
+                let fits = |code: &Option<EdgeCode>, side: usize| code.as_ref().is_none_or(|c| *c == codes[side]);
+                if !fits(&left, LEFT) || !fits(&up, TOP) {
+                    continue;
                 }
 
-                t.rotate();
+                self.placed.push((t, o));
+                self.used[t] = true;
+                if self.solve() {
+                    return true;
+                }
+                self.used[t] = false;
+                self.placed.pop();
             }
-            t.flip();
         }
-    }
 
-    match ret {
-        None => None,
-        Some(t) => {
-            tiles.retain(|t| t.num != tile.num);
-            Some(t)
-        }
+        false
     }
 }
 
-fn get_line(first: &Tile, tiles: &mut Vec<Tile>) -> Vec<Tile> {
-    let mut line = vec![first.clone()];
-    tiles.retain(|t| t.num != first.num);
+// Position in `tiles` and orientation of the tile in each cell of the puzzle.
+pub fn arrange(tiles: &[Tile]) -> Option<Vec<Vec<(usize, D4)>>> {
+    let index = EdgeIndex::new(tiles);
+    let oriented = tiles
+        .iter()
+        .map(|t| D4::all().iter().map(|&o| t.edge_codes(o)).collect_vec())
+        .collect_vec();
+
+    let n = tiles.len();
+    let shapes = (1..=n)
+        .map(|rows| (rows, n / rows))
+        .filter(|&(rows, cols)| rows * cols == n)
+        .sorted_by_key(|&(rows, cols)| (rows as isize - cols as isize).abs());
+
+    for (rows, cols) in shapes {
+        let mut assembler = Assembler {
+            index: &index,
+            oriented: oriented.clone(),
+            rows,
+            cols,
+            placed: Vec::new(),
+            used: vec![false; n],
+        };
 
-    while let Some(tile) = get_tile_from_dir(&line.last().unwrap(), tiles, Dir::Right) {
-        tiles.retain(|t| t.num != tile.num);
-        line.push(tile.clone());
+        if assembler.solve() {
+            return Some(assembler.placed.chunks(cols).map(|line| line.to_vec()).collect());
+        }
     }
 
-    line
+    None
 }
 
-fn gen_tile_image(tiles: &Vec<Tile>) -> Vec<Vec<Tile>> {
-    let mut tiles = tiles.clone();
-    let mut current = get_top_left(&mut tiles);
-    let mut grid: Vec<Vec<_>> = vec![];
+pub fn assemble(tiles: &[Tile]) -> Option<Vec<Vec<View<'_, char>>>> {
+    let grid = arrange(tiles)?
+        .iter()
+        .map(|line| line
+            .iter()
+            .map(|&(t, o)| tiles[t].view(o))
+            .collect())
+        .collect();
 
-    loop {
-        let first = &current;
-        let line = get_line(&current, &mut tiles);
+    Some(grid)
+}
 
-        grid.push(line);
+// One line per row of the puzzle, each cell being the tile id followed by
+// its orientation (see `D4`'s `Display`).
+pub fn arrangement_text(tiles: &[Tile]) -> Option<String> {
+    let text = arrange(tiles)?
+        .iter()
+        .map(|line| line
+            .iter()
+            .map(|&(t, o)| format!("{} {:<5}", tiles[t].num, o.to_string()))
+            .join(" ")
+            .trim_end()
+            .to_string())
+        .join("\n");
 
-        current = match get_tile_from_dir(first, &mut tiles, Dir::Down) {
-            None => break,
-            Some(t) => t
-        };
-    }
+    Some(text)
+}
 
-    grid
+fn gen_tile_image(tiles: &[Tile]) -> Vec<Vec<View<'_, char>>> {
+    assemble(tiles).expect("Tiles can't be assembled")
 }
 
-fn gen_image_from_tiles(tiles: &Vec<Vec<Tile>>) -> Tile {
+fn gen_image_from_tiles(tiles: &[Vec<View<'_, char>>]) -> Tile {
     let mut grid = vec![];
 
     for line in tiles.iter() {
-        for i in 1..9 {
+        for i in 1..line[0].height() - 1 {
             let mut v = Vec::new();
             for col in line {
-                for c in &col.grid[i][1..9] {
-                    v.push(*c);
-                }
+                v.extend(col.row(i).skip(1).take(col.width() - 2));
             }
             grid.push(v);
         }
@@ -293,62 +355,319 @@ fn gen_image(tiles: &Vec<Tile>) -> Tile {
     gen_image_from_tiles(&grid)
 }
 
-fn count_monsters(tile: &Tile) -> Vec<(usize, usize)> {
-    let coords = [
-        (0, 18),
-        (1, 0), (1, 5), (1, 6), (1, 11), (1, 12), (1, 17), (1, 18), (1, 19),
-        (2, 1), (2, 4), (2, 7), (2, 10), (2, 13), (2, 16)
-    ];
-    let mut ret = vec![];
+// Cells of an ASCII picture, spaces are wildcards and any other character
+// must be found as is in the searched grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    cells: Vec<(usize, usize, char)>,
+    width: usize,
+    height: usize,
+}
 
-    for y in 0..tile.grid.len() - 2 {
-        for x in 0..tile.grid[0].len() - 19 {
-            if coords.iter().all(|(yy, xx)| tile.grid[y + yy][x + xx] == '#') {
-                coords.iter().for_each(|(yy, xx)| ret.push((y + yy, x + xx)));
+#[derive(Debug, PartialEq)]
+pub struct PatternError;
+
+impl FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cells = s
+            .lines()
+            .enumerate()
+            .flat_map(|(y, l)| l
+                .chars()
+                .enumerate()
+                .filter(|&(_, c)| c != ' ')
+                .map(move |(x, c)| (y, x, c)))
+            .collect_vec();
+
+        if cells.is_empty() {
+            return Err(PatternError);
+        }
+
+        Ok(Pattern {
+            width: cells.iter().map(|&(_, x, _)| x + 1).max().unwrap(),
+            height: cells.iter().map(|&(y, _, _)| y + 1).max().unwrap(),
+            cells,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overlap {
+    Allow,
+    Disjoint,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub orientation: D4,
+    pub y: usize,
+    pub x: usize,
+    pub cells: Vec<(usize, usize)>,
+}
+
+impl Pattern {
+    fn matches_at(&self, view: &View<'_, char>, y: usize, x: usize) -> bool {
+        self.cells.iter().all(|&(yy, xx, c)| *view.get(y + yy, x + xx) == c)
+    }
+
+    // Every match of the pattern, in any orientation, in grid coordinates.
+    // Orientations giving the same cells (symmetric patterns) are reported
+    // once, and with `Overlap::Disjoint` a match sharing a cell with an
+    // earlier one is dropped.
+    pub fn search(&self, tile: &Tile, overlap: Overlap) -> Vec<Match> {
+        let mut ret: Vec<Match> = Vec::new();
+        let mut seen: HashSet<Vec<(usize, usize)>> = HashSet::new();
+        let mut used: HashSet<(usize, usize)> = HashSet::new();
+
+        for &orientation in D4::all().iter() {
+            // The grid is turned the other way so that the pattern is read as
+            // written, then the cells are mapped back to the grid.
+            let view = tile.view(orientation.inverse());
+            let (w, h) = (view.width(), view.height());
+            if self.height > h || self.width > w {
+                continue;
+            }
+            let back = |y, x| orientation.apply(w, h, y, x);
+
+            for y in 0..=h - self.height {
+                for x in 0..=w - self.width {
+                    if !self.matches_at(&view, y, x) {
+                        continue;
+                    }
+
+                    let cells = self.cells
+                        .iter()
+                        .map(|&(yy, xx, _)| back(y + yy, x + xx))
+                        .sorted()
+                        .collect_vec();
+                    let (a, b) = (back(y, x), back(y + self.height - 1, x + self.width - 1));
+                    let (y, x) = (a.0.min(b.0), a.1.min(b.1));
+                    if !seen.insert(cells.clone()) {
+                        continue;
+                    }
+                    if overlap == Overlap::Disjoint && cells.iter().any(|c| used.contains(c)) {
+                        continue;
+                    }
+
+                    used.extend(cells.iter().copied());
+                    ret.push(Match { orientation, y, x, cells });
+                }
             }
         }
+
+        ret
     }
+}
+
+const SEA_MONSTER: &str = "                  #
+#    ##    ##    ###
+ #  #  #  #  #  #";
+
+fn find_monsters(tile: &Tile) -> Vec<Match> {
+    let monster: Pattern = SEA_MONSTER.parse().unwrap();
+    monster.search(tile, Overlap::Allow)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pixel {
+    Water,
+    Wave,
+    Monster,
+    Boundary(char),
+}
 
-    ret
+// Picture of an assembled image, with tile boundaries drawn as extra lines
+// and the cells of some matches highlighted.
+pub struct ImageView<'a> {
+    image: &'a Tile,
+    highlight: HashSet<(usize, usize)>,
+    rows: Vec<usize>,
+    cols: Vec<usize>,
 }
 
-fn find_monsters(tile: &Tile) -> Vec<(usize, usize)> {
-    let mut tile = tile.clone();
-    loop {
-        for _ in 0..2 {
-            for _ in 0..4 {
-                let monsters = count_monsters(&tile);
-                if !monsters.is_empty() {
-                    return monsters;
+// Image lines and columns where a new tile starts, borders removed.
+pub fn tile_boundaries(grid: &[Vec<View<'_, char>>]) -> (Vec<usize>, Vec<usize>) {
+    let rows = grid
+        .iter()
+        .scan(0, |acc, line| {
+            *acc += line[0].height() - 2;
+            Some(*acc)
+        })
+        .collect_vec();
+    let cols = grid
+        .first()
+        .map(|line| line
+            .iter()
+            .scan(0, |acc, t| {
+                *acc += t.width() - 2;
+                Some(*acc)
+            })
+            .collect_vec())
+        .unwrap_or_default();
+
+    (rows[..rows.len().saturating_sub(1)].to_vec(), cols[..cols.len().saturating_sub(1)].to_vec())
+}
+
+impl<'a> ImageView<'a> {
+    pub fn new(image: &'a Tile) -> ImageView<'a> {
+        ImageView {
+            image,
+            highlight: HashSet::new(),
+            rows: Vec::new(),
+            cols: Vec::new(),
+        }
+    }
+
+    pub fn highlight(mut self, matches: &[Match]) -> ImageView<'a> {
+        self.highlight.extend(matches.iter().flat_map(|m| m.cells.iter().copied()));
+        self
+    }
+
+    pub fn boundaries(mut self, (rows, cols): (Vec<usize>, Vec<usize>)) -> ImageView<'a> {
+        self.rows = rows;
+        self.cols = cols;
+        self
+    }
+
+    fn pixels(&self) -> Vec<Vec<Pixel>> {
+        let mut ret = Vec::new();
+
+        for (y, line) in self.image.grid.iter().enumerate() {
+            if self.rows.contains(&y) {
+                let mut sep = vec![Pixel::Boundary('-'); line.len() + self.cols.len()];
+                for (i, x) in self.cols.iter().enumerate() {
+                    sep[x + i] = Pixel::Boundary('+');
                 }
-                tile.rotate();
+                ret.push(sep);
             }
-            tile.flip();
+
+            let mut v = Vec::new();
+            for (x, &c) in line.iter().enumerate() {
+                if self.cols.contains(&x) {
+                    v.push(Pixel::Boundary('|'));
+                }
+                v.push(match c {
+                    '#' if self.highlight.contains(&(y, x)) => Pixel::Monster,
+                    '#' => Pixel::Wave,
+                    _ => Pixel::Water,
+                });
+            }
+            ret.push(v);
+        }
+
+        ret
+    }
+
+    pub fn to_text(&self) -> String {
+        self.pixels()
+            .iter()
+            .map(|line| line
+                .iter()
+                .map(|p| match p {
+                    Pixel::Water => '.',
+                    Pixel::Wave => '#',
+                    Pixel::Monster => 'O',
+                    Pixel::Boundary(c) => *c,
+                })
+                .collect::<String>())
+            .join("\n")
+    }
+
+    pub fn to_ansi(&self) -> String {
+        self.pixels()
+            .iter()
+            .map(|line| {
+                let mut s = String::new();
+                let mut current = "";
+
+                for p in line {
+                    let (code, c) = match p {
+                        Pixel::Water => ("\x1b[0;34m", '.'),
+                        Pixel::Wave => ("\x1b[0;36m", '#'),
+                        Pixel::Monster => ("\x1b[1;31m", 'O'),
+                        Pixel::Boundary(c) => ("\x1b[0;90m", *c),
+                    };
+                    if code != current {
+                        s.push_str(code);
+                        current = code;
+                    }
+                    s.push(c);
+                }
+
+                s.push_str("\x1b[0m");
+                s
+            })
+            .join("\n")
+    }
+
+    pub fn to_raster(&self, scale: usize) -> Raster {
+        Raster::from_grid(&self.pixels(), scale, |p| match p {
+            Pixel::Water => [16, 48, 96],
+            Pixel::Wave => [64, 160, 208],
+            Pixel::Monster => [224, 48, 32],
+            Pixel::Boundary(_) => [96, 96, 96],
+        })
+    }
+
+    pub fn write_ppm(&self, path: impl AsRef<Path>, scale: usize) -> io::Result<()> {
+        fs::write(path, self.to_raster(scale).to_ppm())
+    }
+
+    pub fn write_png(&self, path: impl AsRef<Path>, scale: usize) -> io::Result<()> {
+        fs::write(path, self.to_raster(scale).to_png())
+    }
+}
+
+// Tiles must all have the same size, up to a rotation.
+pub fn parse_tiles(input: &str) -> Result<Vec<Tile>, TileError> {
+    let tiles: Vec<Tile> = input
+        .split("\n\n")
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.trim_start_matches('\n').parse())
+        .collect::<Result<_, _>>()?;
+
+    if let Some(first) = tiles.first() {
+        let expected = (first.width(), first.height());
+        if let Some(t) = tiles.iter().find(|t| {
+            let size = (t.width(), t.height());
+            size != expected && size != (expected.1, expected.0)
+        }) {
+            return Err(TileError::SizeMismatch { num: t.num, size: (t.width(), t.height()), expected });
         }
     }
+
+    Ok(tiles)
 }
 
 #[aoc_generator(day20)]
 pub fn gen(input: &str) -> Vec<Tile> {
-    input
-        .split("\n\n")
-        .map(Tile::from_str)
-        .collect()
+    parse_tiles(input).unwrap_or_else(|e| panic!("Parsing error: {}", e))
 }
 
+// The corners are taken from the arranged puzzle: when an edge is shared by
+// more than two tiles, counting unmatched edges doesn't find them.
 #[aoc(day20, part1)]
 pub fn solve_part1(tiles: &Vec<Tile>) -> usize {
-    tiles
+    let grid = arrange(tiles).expect("Tiles can't be assembled");
+    let (last_row, last_col) = (grid.len() - 1, grid[0].len() - 1);
+
+    [grid[0][0], grid[0][last_col], grid[last_row][0], grid[last_row][last_col]]
         .iter()
-        .filter(|&t| count_adjacent(t, tiles) == 2)
-        .map(|t| t.num)
+        .map(|&(t, _)| t)
+        .unique()
+        .map(|t| tiles[t].num)
         .product()
 }
 
 #[aoc(day20, part2)]
 pub fn solve_part2(tiles: &Vec<Tile>) -> usize {
     let puzzle = gen_image(&tiles);
-    let monsters = find_monsters(&puzzle);
+    let monsters: HashSet<(usize, usize)> = find_monsters(&puzzle)
+        .into_iter()
+        .flat_map(|m| m.cells)
+        .collect();
 
     puzzle.count_sharp() - monsters.len()
 }
@@ -372,6 +691,253 @@ mod tests {
         assert_eq!(solve_part2(&gen(get_input())), 273);
     }
 
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("Tile 12345:\n#.\n.#".parse::<Tile>().unwrap().num, 12345);
+        assert_eq!("Tile 7:\n#..\n.#.".parse::<Tile>().map(|t| (t.width(), t.height())), Ok((3, 2)));
+        assert_eq!("".parse::<Tile>().unwrap_err(), TileError::MissingHeader);
+        assert_eq!("Tile: 12\n#.".parse::<Tile>().unwrap_err(), TileError::BadHeader("Tile: 12".to_string()));
+        assert_eq!("Tile 12".parse::<Tile>().unwrap_err(), TileError::BadHeader("Tile 12".to_string()));
+        assert_eq!("Tile 12:".parse::<Tile>().unwrap_err(), TileError::Empty(12));
+        assert_eq!("Tile 12:\n#.\n#".parse::<Tile>().unwrap_err(), TileError::Ragged(12));
+
+        assert_eq!(parse_tiles("Tile 1:\n#..\n.#.\n\nTile 2:\n#.\n.#\n#.\n").map(|t| t.len()), Ok(2));
+        assert_eq!(
+            parse_tiles("Tile 1:\n#..\n.#.\n\nTile 2:\n#.\n.#").unwrap_err(),
+            TileError::SizeMismatch { num: 2, size: (2, 2), expected: (3, 2) }
+        );
+    }
+
+    #[test]
+    fn test_view_non_square() {
+        let t: Tile = "Tile 1:\n#..\n.#.".parse().unwrap();
+        let r = t.view(D4::rotation(1));
+        assert_eq!((r.width(), r.height()), (2, 3));
+        assert_eq!(r.to_vec(), vec![vec!['.', '.'], vec!['.', '#'], vec!['#', '.']]);
+        assert_eq!(D4::rotation(3).view(&r.to_vec()).to_vec(), t.grid);
+        assert_eq!(t.view(D4::reflection()).row(0).collect::<String>(), ".#.");
+    }
+
+    // 2 x 3 tiles of 5 x 4 cells, turned in various ways.
+    fn get_input_non_square() -> &'static str {
+        return "Tile 200:
+##..#
+###.#
+...#.
+....#
+
+Tile 201:
+#.##
+.###
+###.
+.##.
+#..#
+
+Tile 202:
+...##
+...#.
+...#.
+.##.#
+
+Tile 203:
+#...
+..#.
+.##.
+.###
+...#
+
+Tile 204:
+.##..
+...##
+.#.#.
+#.#.#
+
+Tile 205:
+#.#.
+.#..
+####
+#.#.
+..#.";
+    }
+
+    #[test]
+    fn test_non_square() {
+        let tiles = gen(get_input_non_square());
+        let image = gen_image(&tiles);
+        let size = (image.width(), image.height());
+
+        assert!(size == (3 * 3, 2 * 2) || size == (2 * 2, 3 * 3));
+        assert_eq!(image.count_sharp(), 23);
+        assert_eq!(solve_part1(&tiles), 200 * 202 * 203 * 205);
+    }
+
+    #[test]
+    fn test_edge_index() {
+        let tiles = gen(get_input());
+        let index = EdgeIndex::new(&tiles);
+        let nums = |v: Vec<usize>| v.into_iter().map(|t| tiles[t].num).sorted().collect_vec();
+
+        assert_eq!(nums(index.corners()), vec![1171, 1951, 2971, 3079]);
+        let center = tiles.iter().position(|t| t.num == 1427).unwrap();
+        assert_eq!(nums(index.neighbours(center)), vec![1489, 2311, 2473, 2729]);
+        assert_eq!(index.border_count(center), 0);
+
+        let code = edge_code("..##.#..#.".chars());
+        assert_eq!(reverse_code(&code), edge_code(".#..#.##..".chars()));
+        assert_eq!(index.tiles_with_edge(&reverse_code(&code)).len(), 2);
+    }
+
+    #[test]
+    fn test_long_edges() {
+        let line = |s: &str| s.repeat(7);
+        let (a, b, c) = (line("#..#.##..."), line(".##.#....#"), line("#.#.#.#.##"));
+        let tiles = gen(&format!("Tile 1:\n{}\n{}\n\nTile 2:\n{}\n{}", a, b, c, b));
+        assert_eq!(tiles[0].width(), 70);
+
+        let code = edge_code(b.chars());
+        assert!(matches!(code, EdgeCode::Cells(_)));
+        assert_eq!(reverse_code(&code), edge_code(b.chars().rev()));
+        let index = EdgeIndex::new(&tiles);
+        assert_eq!(index.tiles_with_edge(&code), &[0, 1]);
+        assert_eq!(index.neighbours(0), vec![1]);
+        assert_eq!(arrange(&tiles).map(|grid| grid.len() * grid[0].len()), Some(2));
+    }
+
+    // 3 x 3 tiles whose inner horizontal borders, and the top border of the
+    // first row, all read "#.##": all 9 tiles share that edge.
+    fn get_input_shared() -> &'static str {
+        return "Tile 100:
+#.##
+....
+..##
+#.##
+
+Tile 101:
+##.#
+.#..
+####
+####
+
+Tile 102:
+##.#
+...#
+#.##
+##.#
+
+Tile 103:
+#..#
+####
+....
+#..#
+
+Tile 104:
+#.##
+.#..
+....
+#.##
+
+Tile 105:
+#..#
+....
+#..#
+#..#
+
+Tile 106:
+.###
+..##
+##.#
+##.#
+
+Tile 107:
+####
+##.#
+.##.
+..##
+
+Tile 108:
+#.##
+###.
+####
+###.";
+    }
+
+    #[test]
+    fn test_shared_edges() {
+        let tiles = gen(get_input_shared());
+        let index = EdgeIndex::new(&tiles);
+        let shared = tiles[0].edge_codes(D4::IDENTITY).iter().map(|c| index.tiles_with_edge(c).len()).max();
+        assert_eq!(shared, Some(9));
+        assert_eq!(index.corners().len(), 0);
+
+        assert_eq!(solve_part1(&tiles), 100 * 102 * 106 * 108);
+        let image = gen_image(&tiles);
+        assert_eq!((image.width(), image.height()), (6, 6));
+        assert_eq!(image.count_sharp(), 17);
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert_eq!("  \n ".parse::<Pattern>(), Err(PatternError));
+
+        let image = gen_image(&gen(get_input()));
+        let monsters = find_monsters(&image);
+        assert_eq!(monsters.len(), 2);
+        assert!(monsters.iter().all(|m| m.orientation == monsters[0].orientation && m.cells.len() == 15));
+        for m in monsters.iter() {
+            assert!(m.cells.iter().all(|&(y, x)| image.grid[y][x] == '#'));
+            assert_eq!((m.y, m.x), (m.cells.iter().map(|c| c.0).min().unwrap(), m.cells.iter().map(|c| c.1).min().unwrap()));
+        }
+
+        let missing: Pattern = "#####\n#####\n#####".parse().unwrap();
+        assert_eq!(missing.search(&image, Overlap::Allow), vec![]);
+        let huge: Pattern = "#".repeat(100).parse().unwrap();
+        assert_eq!(huge.search(&image, Overlap::Allow), vec![]);
+
+        let row: Tile = "Tile 1:\n....\n###.\n....".parse().unwrap();
+        let pair: Pattern = "##".parse().unwrap();
+        let all = pair.search(&row, Overlap::Allow);
+        assert_eq!(all.iter().map(|m| (m.y, m.x)).collect_vec(), vec![(1, 0), (1, 1)]);
+        assert_eq!(pair.search(&row, Overlap::Disjoint).len(), 1);
+
+        let corner: Pattern = "#.\n##".parse().unwrap();
+        let grid: Tile = "Tile 2:\n.#.\n##.\n...".parse().unwrap();
+        let found = corner.search(&grid, Overlap::Allow);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].cells, vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn test_export() {
+        let tiles = gen(get_input());
+        let text = arrangement_text(&tiles).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert_eq!(text.split_whitespace().count(), 18);
+        let placed = text.split_whitespace().step_by(2).map(|n| n.parse::<usize>().unwrap()).sorted().collect_vec();
+        assert_eq!(placed, tiles.iter().map(|t| t.num).sorted().collect_vec());
+
+        let grid = assemble(&tiles).unwrap();
+        let image = gen_image_from_tiles(&grid);
+        let boundaries = tile_boundaries(&grid);
+        assert_eq!(boundaries, (vec![8, 16], vec![8, 16]));
+
+        let monsters = find_monsters(&image);
+        let view = ImageView::new(&image).highlight(&monsters).boundaries(boundaries);
+        let text = view.to_text();
+        assert_eq!(text.lines().count(), 26);
+        assert!(text.lines().all(|l| l.chars().count() == 26));
+        assert_eq!(text.matches('O').count(), 30);
+        assert_eq!(text.lines().nth(8).unwrap(), "--------+--------+--------");
+        let ansi = view.to_ansi();
+        assert_eq!(ansi.matches('O').count(), 30);
+        assert!(ansi.starts_with("\x1b[0;34m.") && ansi.contains("\x1b[1;31mO"));
+
+        let plain = ImageView::new(&image).to_text();
+        assert_eq!(plain.matches('#').count(), image.count_sharp());
+
+        let raster = view.to_raster(2);
+        assert_eq!((raster.width(), raster.height()), (52, 52));
+    }
+
     fn get_input() -> &'static str {
         return "Tile 2311:
 ..##.#..#.
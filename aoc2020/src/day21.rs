@@ -1,6 +1,7 @@
 use nom::lib::std::collections::{HashMap, HashSet};
 use itertools::Itertools;
 use std::ops::Not;
+use crate::assignment::{AssignmentError, Problem};
 
 #[derive(Debug)]
 pub struct Food {
@@ -33,7 +34,7 @@ impl Food {
     }
 }
 
-fn get_correspondences(foods: &[Food]) -> HashMap<String, String> {
+fn get_correspondences(foods: &[Food]) -> Result<HashMap<String, String>, AssignmentError> {
     // allergen with ingredient list
     let mut candidates: HashMap<&String, HashSet<String>> = HashMap::new();
 
@@ -49,20 +50,20 @@ fn get_correspondences(foods: &[Food]) -> HashMap<String, String> {
         }
     }
 
-    let mut res = HashMap::new();
-    while let Some((i, a)) = candidates.iter().find(|(_, h)| h.len() == 1) {
-        let a = a.iter().next().unwrap().clone();
-        res.insert(i.clone(), a.clone());
-
-        for (_, h) in candidates.iter_mut() {
-            h.remove(&a);
-        }
+    // Allergens are the items, ingredients the values.
+    let allergens: Vec<&String> = candidates.keys().copied().sorted().collect();
+    let ingredients: Vec<&String> = foods.iter().flat_map(|f| f.ingredients.iter()).unique().sorted().collect();
+    let mut problem = Problem::new(ingredients.len());
+    for a in allergens.iter() {
+        problem.add_item(candidates[a].iter().map(|i| ingredients.binary_search(&i).unwrap()));
     }
 
-    res
-        .iter()
-        .map(|(&k, v)| (k.clone(), v.clone()))
-        .collect()
+    Ok(problem
+        .solve()?
+        .into_iter()
+        .enumerate()
+        .map(|(a, i)| (allergens[a].clone(), ingredients[i].clone()))
+        .collect())
 }
 
 #[aoc_generator(day21)]
@@ -75,7 +76,7 @@ pub fn gen(input: &str) -> Vec<Food> {
 
 #[aoc(day21, part1)]
 pub fn solve_part1(foods: &Vec<Food>) -> usize {
-    let cor = get_correspondences(foods);
+    let cor = get_correspondences(foods).unwrap_or_else(|e| panic!("No allergen mapping: {}", e));
 
     foods
         .iter()
@@ -88,6 +89,7 @@ pub fn solve_part1(foods: &Vec<Food>) -> usize {
 #[aoc(day21, part2)]
 pub fn solve_part2(foods: &[Food]) -> String {
     get_correspondences(foods)
+        .unwrap_or_else(|e| panic!("No allergen mapping: {}", e))
         .iter()
         .sorted()
         .map(|(_, v)| v)
@@ -110,9 +112,16 @@ sqjhc mxmxvkd sbzzf (contains fish)";
         println!("{:?}", gen(get_input()));
 
         let foods = gen(get_input());
-        let cor = get_correspondences(&foods);
+        let cor = get_correspondences(&foods).unwrap();
 
         println!("{:?}", cor);
+        assert_eq!(cor["fish"], "sqjhc");
+
+        // Nothing tells dairy and fish apart.
+        let foods = gen("a b (contains dairy, fish)");
+        assert!(matches!(get_correspondences(&foods), Err(AssignmentError::Ambiguous { .. })));
+        let foods = gen("a (contains dairy, fish)");
+        assert_eq!(get_correspondences(&foods), Err(AssignmentError::Infeasible(1)));
     }
 
     #[test]
@@ -1,143 +1,623 @@
-use nom::lib::std::collections::{VecDeque, HashSet};
+use nom::lib::std::collections::{VecDeque, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
-#[derive(PartialEq)]
-pub enum Player {
-    P1,
-    P2,
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameError {
+    MissingHeader(usize),
+    BadCard(String),
+    NotEnoughPlayers(usize),
+    DuplicateCard(usize),
+    CardTooLarge(usize),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::MissingHeader(n) => write!(f, "deck {} has no \"Player N:\" header", n + 1),
+            GameError::BadCard(s) => write!(f, "invalid card {:?}", s),
+            GameError::NotEnoughPlayers(n) => write!(f, "{} players, at least 2 are needed", n),
+            GameError::DuplicateCard(c) => write!(f, "card {} appears twice", c),
+            GameError::CardTooLarge(c) => write!(f, "card {} is larger than {}", c, u16::MAX),
+        }
+    }
+}
+
+// How many cards each player takes into a sub-game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sizing {
+    Drawn,
+    Remaining,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rules {
+    Classic,
+    Recursive(Sizing),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ending {
+    LastPlayer,
+    Repetition,
+}
+
+// One per game played, numbered from 1 in the order they start. Sub-games
+// settled by the shortcut or the memo aren't played and have no entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameStats {
+    pub game: usize,
+    pub parent: Option<usize>,
+    pub depth: usize,
+    pub rounds: usize,
+    pub winner: usize,
+    pub ending: Ending,
+}
+
+// Totals over all the games.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub games: usize,
+    pub rounds: usize,
+    pub max_depth: usize,
+    pub loop_wins: usize,
+    pub shortcuts: usize,
+    pub memo_hits: usize,
+}
+
+impl Stats {
+    fn new(games: &[GameStats], shortcuts: usize, memo_hits: usize) -> Stats {
+        Stats {
+            games: games.len(),
+            rounds: games.iter().map(|g| g.rounds).sum(),
+            max_depth: games.iter().map(|g| g.depth).max().unwrap_or(0),
+            loop_wins: games.iter().filter(|g| g.ending == Ending::Repetition).count(),
+            shortcuts,
+            memo_hits,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub winner: usize,
+    pub scores: Vec<usize>,
+    pub games: Vec<GameStats>,
+    pub stats: Stats,
+}
+
+// Players are numbered from 0 within their own game, a sub-game only has the
+// players that drew a card in the round that started it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Game { game: usize, parent: Option<(usize, usize)> },
+    Round { game: usize, round: usize, decks: Vec<Vec<usize>>, drawn: Vec<(usize, usize)> },
+    SubGame { game: usize, round: usize, sub: usize },
+    RoundWon { game: usize, round: usize, winner: usize },
+    Repetition { game: usize, round: usize, winner: usize },
+    GameWon { game: usize, winner: usize, decks: Vec<Vec<usize>> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Log {
+    pub rules: Rules,
+    pub events: Vec<Event>,
+}
+
+fn join(cards: &[usize]) -> String {
+    cards.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn json_list(items: impl Iterator<Item = String>) -> String {
+    format!("[{}]", items.collect::<Vec<_>>().join(","))
+}
+
+fn json_decks(decks: &[Vec<usize>]) -> String {
+    json_list(decks.iter().map(|d| json_list(d.iter().map(|c| c.to_string()))))
+}
+
+impl Log {
+    // Same wording as the puzzle examples, which only use the game numbers
+    // with the recursive rules.
+    pub fn to_verbose(&self) -> String {
+        let recursive = self.rules != Rules::Classic;
+        let mut lines: Vec<String> = Vec::new();
+        let mut parents = Vec::new();
+
+        for e in self.events.iter() {
+            match e {
+                Event::Game { game, parent } => {
+                    parents.push(parent.map(|(g, _)| g));
+                    if recursive {
+                        lines.push(format!("=== Game {} ===", game));
+                        lines.push(String::new());
+                    }
+                }
+                Event::Round { game, round, decks, drawn } => {
+                    if recursive {
+                        lines.push(format!("-- Round {} (Game {}) --", round, game));
+                    } else {
+                        lines.push(format!("-- Round {} --", round));
+                    }
+                    for (i, d) in decks.iter().enumerate() {
+                        lines.push(format!("Player {}'s deck: {}", i + 1, join(d)));
+                    }
+                    for &(i, c) in drawn.iter() {
+                        lines.push(format!("Player {} plays: {}", i + 1, c));
+                    }
+                }
+                Event::SubGame { .. } => {
+                    lines.push("Playing a sub-game to determine the winner...".to_string());
+                    lines.push(String::new());
+                }
+                Event::RoundWon { game, round, winner } => {
+                    if recursive {
+                        lines.push(format!("Player {} wins round {} of game {}!", winner + 1, round, game));
+                    } else {
+                        lines.push(format!("Player {} wins the round!", winner + 1));
+                    }
+                    lines.push(String::new());
+                }
+                Event::Repetition { game, round, winner } => {
+                    lines.push(format!("Round {} of game {} repeats an earlier one, player {} wins!",
+                                       round, game, winner + 1));
+                }
+                Event::GameWon { game, winner, decks } => {
+                    let parent = parents.pop().flatten();
+                    if recursive {
+                        if lines.last().map(|l| l.is_empty()) == Some(true) {
+                            lines.pop();
+                        }
+                        lines.push(format!("The winner of game {} is player {}!", game, winner + 1));
+                        lines.push(String::new());
+                    }
+                    match parent {
+                        Some(p) => lines.push(format!("...anyway, back to game {}.", p)),
+                        None => {
+                            if recursive {
+                                lines.push(String::new());
+                            }
+                            lines.push("== Post-game results ==".to_string());
+                            for (i, d) in decks.iter().enumerate() {
+                                lines.push(format!("Player {}'s deck: {}", i + 1, join(d)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let event = |e: &Event| match e {
+            Event::Game { game, parent } => format!(
+                "{{\"event\":\"game\",\"game\":{},\"parent\":{}}}",
+                game,
+                parent.map_or("null".to_string(), |(g, r)| format!("{{\"game\":{},\"round\":{}}}", g, r)),
+            ),
+            Event::Round { game, round, decks, drawn } => format!(
+                "{{\"event\":\"round\",\"game\":{},\"round\":{},\"decks\":{},\"drawn\":{}}}",
+                game,
+                round,
+                json_decks(decks),
+                json_list(drawn.iter().map(|(i, c)| format!("{{\"player\":{},\"card\":{}}}", i, c))),
+            ),
+            Event::SubGame { game, round, sub } => format!(
+                "{{\"event\":\"sub_game\",\"game\":{},\"round\":{},\"sub_game\":{}}}",
+                game, round, sub,
+            ),
+            Event::RoundWon { game, round, winner } => format!(
+                "{{\"event\":\"round_won\",\"game\":{},\"round\":{},\"winner\":{}}}",
+                game, round, winner,
+            ),
+            Event::Repetition { game, round, winner } => format!(
+                "{{\"event\":\"repetition\",\"game\":{},\"round\":{},\"winner\":{}}}",
+                game, round, winner,
+            ),
+            Event::GameWon { game, winner, decks } => format!(
+                "{{\"event\":\"game_won\",\"game\":{},\"winner\":{},\"decks\":{}}}",
+                game,
+                winner,
+                json_decks(decks),
+            ),
+        };
+
+        let rules = match self.rules {
+            Rules::Classic => "classic",
+            Rules::Recursive(Sizing::Drawn) => "recursive",
+            Rules::Recursive(Sizing::Remaining) => "recursive_remaining",
+        };
+        format!("{{\"rules\":\"{}\",\"events\":{}}}", rules, json_list(self.events.iter().map(event)))
+    }
+
+    // One node per game, edges labelled with the round that spawned the
+    // sub-game.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph combat {\n");
+        let mut rounds = Vec::new();
+
+        for e in self.events.iter() {
+            match e {
+                Event::Game { game, parent } => {
+                    if rounds.len() < *game {
+                        rounds.resize(*game, 0);
+                    }
+                    if let Some((g, r)) = parent {
+                        out.push_str(&format!("    g{} -> g{} [label=\"round {}\"];\n", g, game, r));
+                    }
+                }
+                Event::Round { game, .. } => rounds[game - 1] += 1,
+                Event::GameWon { game, winner, .. } => out.push_str(&format!(
+                    "    g{} [label=\"game {}\\n{} rounds\\nplayer {} wins\"];\n",
+                    game, game, rounds[game - 1], winner + 1,
+                )),
+                _ => {}
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
 }
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct Game {
-    p1: VecDeque<usize>,
-    p2: VecDeque<usize>,
+    decks: Vec<VecDeque<usize>>,
 }
 
-impl Game {
-    fn from_str(s: &str) -> Game {
-        let decks: Vec<VecDeque<usize>> = s
+impl FromStr for Game {
+    type Err = GameError;
+
+    fn from_str(s: &str) -> Result<Game, GameError> {
+        let decks = s
+            .trim()
             .split("\n\n")
-            .map(|s| s
-                .split('\n')
-                .skip(1)
-                .flat_map(str::parse::<usize>)
-                .collect()
-            )
-            .collect();
+            .enumerate()
+            .map(|(i, s)| {
+                let mut lines = s.lines();
+                match lines.next() {
+                    Some(h) if h.starts_with("Player ") && h.ends_with(':') => {}
+                    _ => return Err(GameError::MissingHeader(i)),
+                }
+                lines
+                    .map(|l| l.trim().parse::<usize>().map_err(|_| GameError::BadCard(l.to_string())))
+                    .collect()
+            })
+            .collect::<Result<Vec<VecDeque<usize>>, _>>()?;
+
+        Game::new(decks)
+    }
+}
 
-        Game {
-            p1: decks[0].clone(),
-            p2: decks[1].clone(),
+fn score(deck: &VecDeque<usize>) -> usize {
+    deck
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, n)| (i + 1) * n)
+        .sum()
+}
+
+const BASE: u128 = 0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835;
+
+// Inverse of an odd number modulo 2^128, by Newton's iteration.
+fn inverse(b: u128) -> u128 {
+    let mut x = b;
+    for _ in 0..7 {
+        x = x.wrapping_mul(2u128.wrapping_sub(b.wrapping_mul(x)));
+    }
+    x
+}
+
+// Ring buffer of cards with a polynomial hash over absolute positions: the
+// card pushed as the p-th one adds card * BASE^p, and dividing by BASE^p of
+// the front card gives a hash that only depends on the cards in the deck.
+#[derive(Debug, Clone)]
+struct Deck {
+    cards: Vec<u16>,
+    head: usize,
+    len: usize,
+    hash: u128,
+    push_pow: u128,
+    pop_pow: u128,
+    pop_inv: u128,
+}
+
+impl Deck {
+    fn new(cards: impl Iterator<Item = u16>, capacity: usize) -> Deck {
+        let mut deck = Deck {
+            cards: vec![0; capacity.next_power_of_two()],
+            head: 0,
+            len: 0,
+            hash: 0,
+            push_pow: 1,
+            pop_pow: 1,
+            pop_inv: 1,
+        };
+        for c in cards {
+            deck.push(c);
         }
+        deck
     }
 
-    fn is_finished(&self) -> bool {
-        self.p1.is_empty() || self.p2.is_empty()
+    fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
-    fn score(&self) -> Option<usize> {
-        let deck = if self.p1.is_empty() {
-            Some(&self.p2)
-        } else if self.p2.is_empty() {
-            Some(&self.p1)
-        } else {
-            None
+    fn push(&mut self, card: u16) {
+        let mask = self.cards.len() - 1;
+        self.cards[(self.head + self.len) & mask] = card;
+        self.len += 1;
+        self.hash = self.hash.wrapping_add(self.push_pow.wrapping_mul(card as u128));
+        self.push_pow = self.push_pow.wrapping_mul(BASE);
+    }
+
+    fn pop(&mut self, inv_base: u128) -> u16 {
+        let card = self.cards[self.head];
+        self.head = (self.head + 1) & (self.cards.len() - 1);
+        self.len -= 1;
+        self.hash = self.hash.wrapping_sub(self.pop_pow.wrapping_mul(card as u128));
+        self.pop_pow = self.pop_pow.wrapping_mul(BASE);
+        self.pop_inv = self.pop_inv.wrapping_mul(inv_base);
+        card
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        let mask = self.cards.len() - 1;
+        (0..self.len).map(move |i| self.cards[(self.head + i) & mask])
+    }
+
+    fn state(&self) -> u128 {
+        self.hash.wrapping_mul(self.pop_inv) ^ self.len as u128
+    }
+}
+
+fn state(decks: &[Deck]) -> u128 {
+    decks
+        .iter()
+        .fold(0u128, |h, d| h.rotate_left(47).wrapping_mul(BASE) ^ d.state())
+}
+
+// Decks as the rolling hash and the cards themselves, each deck led by its
+// length. Only the hash picks the bucket, the cards settle collisions.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Position {
+    hash: u128,
+    cards: Vec<u16>,
+}
+
+impl Position {
+    fn new(decks: &[Deck]) -> Position {
+        let mut cards = Vec::new();
+        for d in decks {
+            cards.push(d.len as u16);
+            cards.extend(d.iter());
+        }
+        Position { hash: state(decks), cards }
+    }
+}
+
+impl Hash for Position {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
+impl Game {
+    pub fn new(decks: Vec<VecDeque<usize>>) -> Result<Game, GameError> {
+        if decks.len() < 2 {
+            return Err(GameError::NotEnoughPlayers(decks.len()));
+        }
+
+        let mut seen = HashSet::new();
+        if let Some(&c) = decks.iter().flatten().find(|&&c| !seen.insert(c)) {
+            return Err(GameError::DuplicateCard(c));
+        }
+        if let Some(&c) = decks.iter().flatten().find(|&&c| c > u16::MAX as usize) {
+            return Err(GameError::CardTooLarge(c));
+        }
+
+        Ok(Game { decks })
+    }
+
+    pub fn decks(&self) -> &[VecDeque<usize>] {
+        &self.decks
+    }
+
+    pub fn play(&self, rules: Rules) -> Outcome {
+        self.run(rules, None)
+    }
+
+    // Same game with every event recorded. The shortcuts are turned off so
+    // that all the sub-games show up, as in the puzzle examples.
+    pub fn replay(&self, rules: Rules) -> (Outcome, Log) {
+        let mut events = Vec::new();
+        let outcome = self.run(rules, Some(&mut events));
+        (outcome, Log { rules, events })
+    }
+
+    fn run(&self, rules: Rules, log: Option<&mut Vec<Event>>) -> Outcome {
+        let total = self.decks.iter().map(|d| d.len()).sum();
+        let mut engine = Engine {
+            rules,
+            games: Vec::new(),
+            shortcuts: 0,
+            memo_hits: 0,
+            inv_base: inverse(BASE),
+            memo: HashMap::new(),
+            log,
         };
+        let mut decks: Vec<Deck> = self.decks
+            .iter()
+            .map(|d| Deck::new(d.iter().map(|&c| c as u16), total))
+            .collect();
+        let winner = engine.play(&mut decks, 1, None);
 
-        deck
-            .map(|deck| deck
+        Outcome {
+            winner,
+            scores: decks
                 .iter()
-                .rev()
-                .enumerate()
-                .map(|(i, n)| (i + 1) * n)
-                .sum())
-    }
-
-    fn play_highest(&mut self) {
-        if !self.is_finished() {
-            let n1 = self.p1.pop_front().unwrap();
-            let n2 = self.p2.pop_front().unwrap();
-
-            if n1 > n2 {
-                self.p1.push_back(n1);
-                self.p1.push_back(n2);
-            } else {
-                self.p2.push_back(n2);
-                self.p2.push_back(n1);
-            }
+                .map(|d| score(&d.iter().map(|c| c as usize).collect()))
+                .collect(),
+            stats: Stats::new(&engine.games, engine.shortcuts, engine.memo_hits),
+            games: engine.games,
         }
     }
+}
 
-    fn play_remaining(&mut self) {
-        if !self.is_finished() {
-            let n1 = self.p1.pop_front().unwrap();
-            let n2 = self.p2.pop_front().unwrap();
+fn snapshot(decks: &[Deck]) -> Vec<Vec<usize>> {
+    decks.iter().map(|d| d.iter().map(|c| c as usize).collect()).collect()
+}
 
-            let mut game = self.clone();
-            game.p1.resize(n1, 0);
-            game.p2.resize(n2, 0);
+struct Engine<'a> {
+    rules: Rules,
+    games: Vec<GameStats>,
+    shortcuts: usize,
+    memo_hits: usize,
+    inv_base: u128,
+    memo: HashMap<Position, usize>,
+    log: Option<&'a mut Vec<Event>>,
+}
 
-            let player = game.play_part2();
-            match player {
-                Player::P1 => {
-                    self.p1.push_back(n1);
-                    self.p1.push_back(n2);
-                }
-                Player::P2 => {
-                    self.p2.push_back(n2);
-                    self.p2.push_back(n1);
-                }
-            }
+impl Engine<'_> {
+    fn record(&mut self, event: impl FnOnce() -> Event) {
+        if let Some(log) = self.log.as_mut() {
+            log.push(event());
         }
     }
 
-    fn play_part2(&mut self) -> Player {
-        let mut h: HashSet<Game> = HashSet::new();
+    // Players with no cards left are out. When a position repeats, the first
+    // player still in the game wins.
+    fn play(&mut self, decks: &mut [Deck], depth: usize, parent: Option<(usize, usize)>) -> usize {
+        let mut seen: HashSet<Position> = HashSet::new();
+        let game = self.games.len() + 1;
+        self.games.push(GameStats {
+            game,
+            parent: parent.map(|(g, _)| g),
+            depth,
+            rounds: 0,
+            winner: 0,
+            ending: Ending::LastPlayer,
+        });
+        self.record(|| Event::Game { game, parent });
+
+        let mut round = 0;
+        let winner = loop {
+            round += 1;
+            let active: Vec<usize> = (0..decks.len()).filter(|&i| !decks[i].is_empty()).collect();
+            if active.len() == 1 {
+                break active[0];
+            }
+            if let Rules::Recursive(_) = self.rules {
+                if !seen.insert(Position::new(decks)) {
+                    self.games[game - 1].ending = Ending::Repetition;
+                    self.record(|| Event::Repetition { game, round, winner: active[0] });
+                    break active[0];
+                }
+            }
 
-        while !self.is_finished() {
-            if h.contains(&self) {
-                return Player::P1;
+            self.games[game - 1].rounds += 1;
+            let before = self.log.as_ref().map(|_| snapshot(decks));
+            let drawn: Vec<(usize, u16)> = active
+                .iter()
+                .map(|&i| (i, decks[i].pop(self.inv_base)))
+                .collect();
+            if let Some(before) = before {
+                let drawn = drawn.iter().map(|&(i, c)| (i, c as usize)).collect();
+                self.record(|| Event::Round { game, round, decks: before, drawn });
             }
-            h.insert(self.clone());
-
-            let n1 = *self.p1.front().unwrap();
-            let n2 = *self.p2.front().unwrap();
-            if self.p1.len() - 1 >= n1 && self.p2.len() - 1 >= n2 {
-                self.play_remaining();
-            } else {
-                self.play_highest();
+            let winner = self.round_winner(decks, &drawn, depth, (game, round));
+            self.record(|| Event::RoundWon { game, round, winner });
+
+            let mut cards: Vec<u16> = drawn.iter().filter(|&&(i, _)| i != winner).map(|&(_, c)| c).collect();
+            cards.sort_unstable_by(|a, b| b.cmp(a));
+            let own = drawn.iter().find(|&&(i, _)| i == winner).unwrap().1;
+            decks[winner].push(own);
+            for c in cards {
+                decks[winner].push(c);
             }
+        };
+
+        self.games[game - 1].winner = winner;
+        if self.log.is_some() {
+            let decks = snapshot(decks);
+            self.record(|| Event::GameWon { game, winner, decks });
+        }
+        winner
+    }
+
+    fn round_winner(&mut self, decks: &[Deck], drawn: &[(usize, u16)], depth: usize, at: (usize, usize)) -> usize {
+        let highest = drawn.iter().max_by_key(|&&(_, c)| c).unwrap().0;
+
+        let sizing = match self.rules {
+            Rules::Recursive(sizing) if drawn.iter().all(|&(i, c)| decks[i].len >= c as usize) => sizing,
+            _ => return highest,
+        };
+
+        let sizes: Vec<usize> = drawn
+            .iter()
+            .map(|&(i, c)| match sizing {
+                Sizing::Drawn => c as usize,
+                Sizing::Remaining => decks[i].len,
+            })
+            .collect();
+        let total = sizes.iter().sum();
+        let mut sub: Vec<Deck> = drawn
+            .iter()
+            .zip(sizes.iter())
+            .map(|(&(i, _), &n)| Deck::new(decks[i].iter().take(n), total))
+            .collect();
+
+        drawn[self.sub_game(&mut sub, depth + 1, at)].0
+    }
+
+    // With N distinct cards the highest one is at least N - 1, so it can
+    // never be the card of a recursive round (that needs N - 1 more cards).
+    // Whoever holds it never loses it, and if that is the first player they
+    // also win on a repeated position.
+    fn sub_game(&mut self, decks: &mut [Deck], depth: usize, at: (usize, usize)) -> usize {
+        if self.log.is_some() {
+            let (game, round) = at;
+            let sub = self.games.len() + 1;
+            self.record(|| Event::SubGame { game, round, sub });
+            return self.play(decks, depth, Some(at));
+        }
+
+        let max = decks.iter().flat_map(|d| d.iter()).max();
+        if max.is_some() && decks[0].iter().max() == max {
+            self.shortcuts += 1;
+            return 0;
         }
 
-        if self.p1.is_empty() {
-            Player::P2
-        } else {
-            Player::P1
+        let key = Position::new(decks);
+        if let Some(&w) = self.memo.get(&key) {
+            self.memo_hits += 1;
+            return w;
         }
+        let w = self.play(decks, depth, Some(at));
+        self.memo.insert(key, w);
+        w
     }
 }
 
 #[aoc_generator(day22)]
 pub fn gen(input: &str) -> Game {
-    Game::from_str(input)
+    input.parse().unwrap_or_else(|e| panic!("Parsing error: {}", e))
 }
 
 #[aoc(day22, part1)]
-pub fn solve_part1(game: &Game) -> Option<usize> {
-    let mut game = game.clone();
-
-    while !game.is_finished() {
-        game.play_highest();
-    }
-
-    game.score()
+pub fn solve_part1(game: &Game) -> usize {
+    let outcome = game.play(Rules::Classic);
+    outcome.scores[outcome.winner]
 }
 
 #[aoc(day22, part2)]
-pub fn solve_part2(game: &Game) -> Option<usize> {
-    let mut game = game.clone();
-
-    game.play_part2();
-    game.score()
+pub fn solve_part2(game: &Game) -> usize {
+    let outcome = game.play(Rules::Recursive(Sizing::Drawn));
+    outcome.scores[outcome.winner]
 }
 
 #[cfg(test)]
@@ -162,17 +642,124 @@ Player 2:
 
     #[test]
     fn test_gen() {
-        println!("{:?}", gen(get_input()));
+        assert_eq!(gen(get_input()).decks().len(), 2);
+        assert_eq!("Player 1:\n1\n\n2".parse::<Game>(), Err(GameError::MissingHeader(1)));
+        assert_eq!("Player 1:\n1\n\nPlayer 2:\nx".parse::<Game>(), Err(GameError::BadCard("x".to_string())));
+        assert_eq!("Player 1:\n1".parse::<Game>(), Err(GameError::NotEnoughPlayers(1)));
+        assert_eq!("Player 1:\n1\n\nPlayer 2:\n1".parse::<Game>(), Err(GameError::DuplicateCard(1)));
+        assert_eq!("Player 1:\n1\n\nPlayer 2:\n70000".parse::<Game>(), Err(GameError::CardTooLarge(70000)));
     }
 
     #[test]
     fn test_part1() {
-        assert_eq!(solve_part1(&gen(get_input())), Some(306));
+        assert_eq!(solve_part1(&gen(get_input())), 306);
     }
 
     #[test]
     fn test_part2() {
-        assert_eq!(solve_part2(&gen(get_input())), Some(291));
+        assert_eq!(solve_part2(&gen(get_input())), 291);
     }
-}
 
+    #[test]
+    fn test_outcome() {
+        let game = gen(get_input());
+
+        let classic = game.play(Rules::Classic);
+        assert_eq!(classic.winner, 1);
+        assert_eq!(classic.scores, vec![0, 306]);
+        assert_eq!(classic.stats, Stats { games: 1, rounds: 29, max_depth: 1, ..Stats::default() });
+
+        let recursive = game.play(Rules::Recursive(Sizing::Drawn));
+        assert_eq!(recursive.scores, vec![0, 291]);
+        assert_eq!(recursive.stats, Stats { games: 4, rounds: 28, max_depth: 3, loop_wins: 0, shortcuts: 0, memo_hits: 1 });
+        let games: Vec<_> = recursive.games.iter().map(|g| (g.game, g.parent, g.depth, g.rounds, g.winner)).collect();
+        assert_eq!(games, vec![(1, None, 1, 17, 1), (2, Some(1), 2, 6, 1), (3, Some(1), 2, 4, 1), (4, Some(3), 3, 1, 1)]);
+
+        let remaining = game.play(Rules::Recursive(Sizing::Remaining));
+        assert_eq!(remaining.scores.iter().filter(|&&s| s > 0).count(), 1);
+        assert!(remaining.stats.max_depth > 1);
+
+        let looping = gen("Player 1:\n43\n19\n\nPlayer 2:\n2\n29\n14");
+        let outcome = looping.play(Rules::Recursive(Sizing::Drawn));
+        assert_eq!(outcome.winner, 0);
+        assert_eq!(outcome.stats.loop_wins, 1);
+        assert_eq!(outcome.games, vec![GameStats {
+            game: 1,
+            parent: None,
+            depth: 1,
+            rounds: 6,
+            winner: 0,
+            ending: Ending::Repetition,
+        }]);
+
+        let three = gen("Player 1:\n1\n6\n\nPlayer 2:\n5\n2\n\nPlayer 3:\n3\n4");
+        let outcome = three.play(Rules::Classic);
+        assert_eq!(outcome.winner, 0);
+        assert_eq!(outcome.scores, vec![91, 0, 0]);
+        assert_eq!(outcome.stats.rounds, 5);
+
+        // Player 1 holds 9 in the sub-game started by drawing 2 and 1.
+        let shortcut = gen("Player 1:\n2\n9\n3\n\nPlayer 2:\n1\n4");
+        let outcome = shortcut.play(Rules::Recursive(Sizing::Drawn));
+        assert_eq!(outcome.stats.shortcuts, 1);
+        assert_eq!(outcome.stats.games, 1);
+    }
+
+    #[test]
+    fn test_deck() {
+        let inv = inverse(BASE);
+        assert_eq!(BASE.wrapping_mul(inv), 1);
+
+        let mut a = Deck::new([3, 1, 2].iter().copied(), 3);
+        let b = Deck::new([1, 2, 3].iter().copied(), 3);
+        let c = a.pop(inv);
+        a.push(c);
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(a.state(), b.state());
+        assert_ne!(a.state(), Deck::new([2, 1, 3].iter().copied(), 3).state());
+        assert_ne!(state(&[a.clone(), b.clone()]), state(&[b, Deck::new([1, 2].iter().copied(), 3)]));
+        assert_eq!(Position::new(&[a]).cards, vec![3, 1, 2, 3]);
+
+        // Same hash, different cards: still two positions.
+        let mut seen = HashSet::new();
+        assert!(seen.insert(Position { hash: 7, cards: vec![1, 1] }));
+        assert!(seen.insert(Position { hash: 7, cards: vec![1, 2] }));
+        assert!(!seen.insert(Position { hash: 7, cards: vec![1, 1] }));
+    }
+
+    #[test]
+    fn test_log() {
+        let game = gen(get_input());
+
+        let (outcome, log) = game.replay(Rules::Recursive(Sizing::Drawn));
+        assert_eq!(outcome.stats.games, 5);
+        assert_eq!(outcome.stats.memo_hits, 0);
+        let text = log.to_verbose();
+        assert!(text.starts_with("=== Game 1 ===\n\n-- Round 1 (Game 1) --\nPlayer 1's deck: 9, 2, 6, 3, 1\n"));
+        assert!(text.contains("Player 2 plays: 3\nPlaying a sub-game to determine the winner...\n\n\
+                               === Game 2 ===\n\n-- Round 1 (Game 2) --\nPlayer 1's deck: 9, 8, 5, 2\n"));
+        assert!(text.contains("Player 2 wins round 6 of game 2!\nThe winner of game 2 is player 2!\n\n\
+                               ...anyway, back to game 1.\nPlayer 2 wins round 9 of game 1!\n"));
+        assert!(text.ends_with("The winner of game 1 is player 2!\n\n\n== Post-game results ==\n\
+                                Player 1's deck: \nPlayer 2's deck: 7, 5, 6, 2, 4, 1, 10, 8, 9, 3"));
+
+        let dot = log.to_dot();
+        assert!(dot.contains("g1 -> g3 [label=\"round 13\"];\n    g3 -> g4 [label=\"round 2\"];"));
+        assert!(dot.contains("g1 [label=\"game 1\\n17 rounds\\nplayer 2 wins\"];"));
+
+        let json = log.to_json();
+        assert!(json.starts_with("{\"rules\":\"recursive\",\"events\":[{\"event\":\"game\",\"game\":1,\"parent\":null},\
+                                  {\"event\":\"round\",\"game\":1,\"round\":1,\"decks\":[[9,2,6,3,1],[5,8,4,7,10]],\
+                                  \"drawn\":[{\"player\":0,\"card\":9},{\"player\":1,\"card\":5}]}"));
+        assert!(json.contains("{\"event\":\"game\",\"game\":2,\"parent\":{\"game\":1,\"round\":9}}"));
+
+        let (_, log) = game.replay(Rules::Classic);
+        let text = log.to_verbose();
+        assert!(text.starts_with("-- Round 1 --\nPlayer 1's deck: 9, 2, 6, 3, 1\n"));
+        assert!(text.ends_with("Player 2 wins the round!\n\n== Post-game results ==\n\
+                                Player 1's deck: \nPlayer 2's deck: 3, 2, 10, 6, 8, 5, 9, 4, 7, 1"));
+
+        let (_, log) = gen("Player 1:\n43\n19\n\nPlayer 2:\n2\n29\n14").replay(Rules::Recursive(Sizing::Drawn));
+        assert!(log.events.iter().any(|e| matches!(e, Event::Repetition { game: 1, winner: 0, .. })));
+    }
+}
@@ -1,93 +1,237 @@
-use itertools::Itertools;
-use std::ops::Not;
+use std::fmt;
 
+const PART2_CUPS: usize = 1_000_000;
+const PART2_MOVES: usize = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CupsError {
+    Empty,
+    BadLabel(String),
+    Duplicate(u32),
+    TooFewCups { cups: usize, pick: usize },
+    TooManyCups(usize),
+    MissingCup(u32),
+}
+
+impl fmt::Display for CupsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CupsError::Empty => write!(f, "no cups"),
+            CupsError::BadLabel(s) => write!(f, "invalid label {:?}, labels are positive integers", s),
+            CupsError::Duplicate(n) => write!(f, "cup {} appears twice", n),
+            CupsError::TooFewCups { cups, pick } => write!(f, "picking {} cups needs more than {} cups", pick, cups),
+            CupsError::TooManyCups(n) => write!(f, "{} cups don't fit in u32 labels", n),
+            CupsError::MissingCup(n) => write!(f, "no cup {}", n),
+        }
+    }
+}
+
+impl std::error::Error for CupsError {}
+
+// Either one digit per cup ("389125467") or a comma separated list.
+pub fn parse_labels(s: &str) -> Result<Vec<u32>, CupsError> {
+    let s = s.trim();
+    let labels: Vec<u32> = if s.contains(',') {
+        s.split(',')
+            .map(|l| l.trim())
+            .map(|l| l.parse().ok().filter(|&n| n > 0).ok_or_else(|| CupsError::BadLabel(l.to_string())))
+            .collect::<Result<_, _>>()?
+    } else {
+        s.chars()
+            .map(|c| c.to_digit(10).filter(|&n| n > 0).ok_or_else(|| CupsError::BadLabel(c.to_string())))
+            .collect::<Result<_, _>>()?
+    };
+
+    if labels.is_empty() {
+        return Err(CupsError::Empty);
+    }
+    Ok(labels)
+}
+
+// Circle stored as a successor array. Cups are numbered 1 to len in label
+// order, so sparse labels don't cost memory: the given labels sorted, then
+// the extra cups.
 #[derive(Debug, Clone)]
-pub struct Crab {
-    current: usize,
-    cups: Vec<usize>,
+pub struct Cups {
+    next: Vec<u32>,
+    labels: Vec<u32>,
+    current: u32,
+    pick: usize,
+    moves: usize,
+    len: usize,
 }
 
-impl Crab {
-    fn from(s: &str) -> Crab {
-        let nums = s
-            .chars()
-            .map(|c| c.to_digit(10).unwrap() as usize)
-            .collect_vec();
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    next: Vec<u32>,
+    current: u32,
+    moves: usize,
+}
+
+impl Cups {
+    // The labels in order, then cups numbered from the highest label up
+    // until there are `total` cups. The first label is the current cup.
+    pub fn new(labels: &[u32], total: usize) -> Result<Cups, CupsError> {
+        if labels.is_empty() {
+            return Err(CupsError::Empty);
+        }
+        let len = total.max(labels.len());
+        let mut sorted = labels.to_vec();
+        sorted.sort_unstable();
+        if let Some(w) = sorted.windows(2).find(|w| w[0] == w[1]) {
+            return Err(CupsError::Duplicate(w[0]));
+        }
+        if *sorted.last().unwrap() as usize + len - labels.len() > u32::MAX as usize {
+            return Err(CupsError::TooManyCups(len));
+        }
+
+        let mut cups = Cups { next: vec![0; len + 1], labels: sorted, current: 0, pick: 3, moves: 0, len };
+        let order: Vec<u32> = labels
+            .iter()
+            .map(|&l| cups.index(l).unwrap())
+            .chain(labels.len() as u32 + 1..=len as u32)
+            .collect();
+        for (&c, &n) in order.iter().zip(order.iter().cycle().skip(1)) {
+            cups.next[c as usize] = n;
+        }
+        cups.current = order[0];
+
+        Ok(cups)
+    }
 
-        let mut cups = vec![0; nums.len() + 1];
-        for (i, cup) in nums.iter().enumerate() {
-            cups[*cup] = nums[(i + 1) % nums.len()];
+    fn index(&self, label: u32) -> Option<u32> {
+        let max = *self.labels.last().unwrap();
+        match self.labels.binary_search(&label) {
+            Ok(i) => Some(i as u32 + 1),
+            Err(_) if label > max && (label - max) as usize <= self.len - self.labels.len() =>
+                Some(self.labels.len() as u32 + label - max),
+            Err(_) => None,
         }
+    }
 
-        Crab {
-            current: nums[0],
-            cups,
+    fn label(&self, index: u32) -> u32 {
+        let given = self.labels.len() as u32;
+        if index <= given {
+            self.labels[index as usize - 1]
+        } else {
+            self.labels[given as usize - 1] + index - given
         }
     }
 
-    fn play(&mut self) {
-        let current = self.current;
-        let n1 = self.cups[current];
-        let n2 = self.cups[n1];
-        let n3 = self.cups[n2];
-        self.cups[current] = self.cups[n3];
+    pub fn with_pick(mut self, pick: usize) -> Result<Cups, CupsError> {
+        if pick == 0 || pick + 2 > self.len {
+            return Err(CupsError::TooFewCups { cups: self.len, pick });
+        }
+        self.pick = pick;
+        Ok(self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn current(&self) -> u32 {
+        self.label(self.current)
+    }
+
+    pub fn moves(&self) -> usize {
+        self.moves
+    }
+
+    pub fn play(&mut self) {
+        assert!(self.pick + 2 <= self.len, "{}", CupsError::TooFewCups { cups: self.len, pick: self.pick });
+
+        let current = self.current as usize;
+        let first = self.next[current];
+        let mut last = first;
+        for _ in 1..self.pick {
+            last = self.next[last as usize];
+        }
+        self.next[current] = self.next[last as usize];
+
+        let picked = |cup: u32, next: &[u32]| {
+            let mut c = first;
+            loop {
+                if c == cup {
+                    return true;
+                }
+                if c == last {
+                    return false;
+                }
+                c = next[c as usize];
+            }
+        };
 
         let mut dest = current;
         loop {
-            dest = dest - 1;
-            if dest == 0 {
-                dest = self.cups.len() - 1;
-            }
-            if [n1, n2, n3].contains(&dest).not() {
+            dest = if dest <= 1 { self.len } else { dest - 1 };
+            if !picked(dest as u32, &self.next) {
                 break;
             }
         }
 
-        self.cups[n3] = self.cups[dest];
-        self.cups[dest] = n1;
-        self.current = self.cups[self.current];
+        self.next[last as usize] = self.next[dest];
+        self.next[dest] = first;
+        self.current = self.next[current];
+        self.moves += 1;
     }
-}
 
+    pub fn run(&mut self, moves: usize) {
+        for _ in 0..moves {
+            self.play();
+        }
+    }
 
-#[aoc_generator(day23)]
-pub fn gen(input: &str) -> Crab {
-    Crab::from(input)
-}
+    // Every other cup clockwise from `cup`, None if there is no such cup.
+    pub fn after(&self, cup: u32) -> Option<impl Iterator<Item = u32> + '_> {
+        let mut c = self.index(cup)?;
+        Some((1..self.len).map(move |_| {
+            c = self.next[c as usize];
+            self.label(c)
+        }))
+    }
 
-#[aoc(day23, part1)]
-pub fn solve_part1(crab: &Crab) -> usize {
-    let mut crab = crab.clone();
-    for _ in 0..100 {
-        crab.play();
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            next: self.next.clone(),
+            current: self.current,
+            moves: self.moves,
+        }
     }
 
-    let mut cur = 1;
-    let mut ret = 0;
-    while crab.cups[cur] != 1 {
-        ret = (ret * 10) + crab.cups[cur];
-        cur = crab.cups[cur];
+    pub fn restore(&mut self, checkpoint: &Checkpoint) {
+        assert_eq!(self.next.len(), checkpoint.next.len(), "Checkpoint of another game");
+        self.next.copy_from_slice(&checkpoint.next);
+        self.current = checkpoint.current;
+        self.moves = checkpoint.moves;
     }
+}
 
-    ret
+#[aoc_generator(day23)]
+pub fn gen(input: &str) -> Vec<u32> {
+    parse_labels(input).unwrap_or_else(|e| panic!("Parsing error: {}", e))
 }
 
-#[aoc(day23, part2)]
-pub fn solve_part2(crab: &Crab) -> usize {
-    let mut crab = crab.clone();
-    let pos = crab.cups.iter().position(|&x| x == crab.current).unwrap();
+#[aoc(day23, part1)]
+pub fn solve_part1(labels: &[u32]) -> Result<String, CupsError> {
+    let mut cups = Cups::new(labels, labels.len())?;
+    cups.run(100);
 
-    crab.cups[pos] = crab.cups.len();
-    for i in crab.cups.len()..1000000 {
-        crab.cups.push(i + 1);
-    }
-    crab.cups.push(crab.current);
+    let labels = cups.after(1).ok_or(CupsError::MissingCup(1))?.map(|c| c.to_string()).collect();
+    Ok(labels)
+}
 
-    for _ in 0..10000000 {
-        crab.play();
-    }
+#[aoc(day23, part2)]
+pub fn solve_part2(labels: &[u32]) -> Result<usize, CupsError> {
+    let mut cups = Cups::new(labels, PART2_CUPS)?;
+    cups.run(PART2_MOVES);
 
-    crab.cups[1] * crab.cups[crab.cups[1]]
+    let product = cups.after(1).ok_or(CupsError::MissingCup(1))?.take(2).map(|c| c as usize).product();
+    Ok(product)
 }
 
 #[cfg(test)]
@@ -100,16 +244,60 @@ mod tests {
 
     #[test]
     fn test_gen() {
-        println!("{:?}", gen(get_input()));
+        assert_eq!(gen(get_input()), vec![3, 8, 9, 1, 2, 5, 4, 6, 7]);
+        assert_eq!(parse_labels("3, 12,7"), Ok(vec![3, 12, 7]));
+        assert_eq!(parse_labels("38a"), Err(CupsError::BadLabel("a".to_string())));
+        assert_eq!(parse_labels("3,0"), Err(CupsError::BadLabel("0".to_string())));
+        assert_eq!(parse_labels(""), Err(CupsError::Empty));
+        assert_eq!(Cups::new(&[3, 8, 3], 3).unwrap_err(), CupsError::Duplicate(3));
+        assert_eq!(Cups::new(&[1, 2, 3], 3).unwrap().with_pick(2).unwrap_err(),
+                   CupsError::TooFewCups { cups: 3, pick: 2 });
     }
 
     #[test]
     fn test_part1() {
-        assert_eq!(solve_part1(&gen(get_input())), 67384529);
+        assert_eq!(solve_part1(&gen(get_input())), Ok("67384529".to_string()));
+        assert_eq!(solve_part1(&[3, 10, 1, 2, 5]), Ok("25310".to_string()));
+        assert_eq!(solve_part1(&[3, 4, 2, 5, 6]), Err(CupsError::MissingCup(1)));
     }
 
     #[test]
     fn test_part2() {
-        assert_eq!(solve_part2(&gen(get_input())), 149245887792);
+        assert_eq!(solve_part2(&gen(get_input())), Ok(149245887792));
+    }
+
+    #[test]
+    fn test_engine() {
+        let labels = gen(get_input());
+        let mut cups = Cups::new(&labels, 9).unwrap();
+        cups.run(10);
+        assert_eq!(cups.after(1).unwrap().collect::<Vec<_>>(), vec![9, 2, 6, 5, 8, 3, 7, 4]);
+        assert_eq!(cups.current(), 8);
+        assert!(cups.after(10).is_none());
+
+        let checkpoint = cups.checkpoint();
+        cups.run(90);
+        let end: Vec<u32> = cups.after(1).unwrap().collect();
+        cups.restore(&checkpoint);
+        assert_eq!(cups.moves(), 10);
+        cups.run(90);
+        assert_eq!(cups.after(1).unwrap().collect::<Vec<_>>(), end);
+
+        // Labels with gaps and extra cups numbered after the highest label.
+        let mut cups = Cups::new(&[10, 30, 20], 5).unwrap().with_pick(1).unwrap();
+        assert_eq!(cups.after(10).unwrap().collect::<Vec<_>>(), vec![30, 20, 31, 32]);
+        cups.play();
+        assert_eq!(cups.after(10).unwrap().collect::<Vec<_>>(), vec![20, 31, 32, 30]);
+        cups.play();
+        assert_eq!(cups.current(), 32);
+        assert_eq!(cups.after(32).unwrap().collect::<Vec<_>>(), vec![30, 10, 31, 20]);
+        assert_eq!(Cups::new(&[3, 8, 8], 3).unwrap_err(), CupsError::Duplicate(8));
+
+        // Sparse labels are numbered densely.
+        let cups = Cups::new(&[1, 4_000_000_000], 2).unwrap();
+        assert_eq!(cups.next.len(), 3);
+        assert_eq!(cups.after(4_000_000_000).unwrap().collect::<Vec<_>>(), vec![1]);
+        assert!(cups.after(4_000_000_001).is_none());
+        assert_eq!(Cups::new(&[u32::MAX], 2).unwrap_err(), CupsError::TooManyCups(2));
     }
 }
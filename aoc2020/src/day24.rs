@@ -1,132 +1,179 @@
-use nom::lib::std::collections::HashMap;
-use itertools::Itertools;
-
-type Tiles = HashMap<Point, Color>;
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-pub struct Point {
-    x: isize,
-    y: isize,
-}
+use std::str::FromStr;
+use crate::animation::Frame;
+use crate::cellular::Rule;
+use std::collections::HashSet;
+use crate::hex::{parse_path, Axial, Doubled, Orientation, PathError};
+use crate::simulation::Simulation;
 
 #[derive(Debug)]
 pub struct Tile {
-    dirs: Vec<Point>
+    dirs: Vec<Axial>
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum Color {
-    Black,
-    White,
-}
+impl FromStr for Tile {
+    type Err = PathError;
 
-const DIRS: [Point; 6] = [
-    Point { x: 2, y: 0 },
-    Point { x: -2, y: 0 },
-    Point { x: 1, y: 1 },
-    Point { x: -1, y: 1 },
-    Point { x: 1, y: -1 },
-    Point { x: -1, y: -1 },
-];
-
-
-impl Color {
-    fn flip(self) -> Color {
-        if self == Color::Black {
-            Color::White
-        } else {
-            Color::Black
-        }
+    fn from_str(s: &str) -> Result<Tile, PathError> {
+        Ok(Tile {
+            dirs: parse_path(s, Orientation::PointyTop)?
+        })
     }
 }
 
 impl Tile {
-    fn from(s: &str) -> Tile {
-        let mut s = s;
-        let mut v = Vec::new();
-
-        loop {
-            if s.is_empty() {
-                break;
-            }
-            let (t, p) = match &s[0..1] {
-                "e" => (&s[1..], Point { x: 2, y: 0 }),
-                "w" => (&s[1..], Point { x: -2, y: 0 }),
-                _ => (&s[2..], match &s[0..2] {
-                    "ne" => Point { x: 1, y: 1 },
-                    "nw" => Point { x: -1, y: 1 },
-                    "se" => Point { x: 1, y: -1 },
-                    "sw" => Point { x: -1, y: -1 },
-                    _ => panic!("Invalid input")
-                })
-            };
-
-            v.push(p);
-            s = t;
-        }
-
-        Tile {
-            dirs: v
-        }
-    }
-
-    fn identify(&self) -> Point {
+    fn identify(&self) -> Axial {
         self
             .dirs
             .iter()
-            .fold(Point { x: 0, y: 0 }, |acc, p|
-                Point { x: acc.x + p.x, y: acc.y + p.y })
+            .fold(Axial::ORIGIN, |acc, &p| acc + p)
     }
 }
 
 
-fn flip_all(tiles: &Vec<Tile>) -> Tiles {
-    let mut h = HashMap::new();
+fn flip_all(tiles: &[Tile]) -> HashSet<Axial> {
+    let mut h = HashSet::new();
 
     tiles
         .iter()
         .map(Tile::identify)
         .for_each(|p| {
-            let col = h.entry(p).or_insert(Color::White);
-            *col = col.flip();
+            if !h.remove(&p) {
+                h.insert(p);
+            }
         });
 
     h
 }
 
-fn count_black_neighbors(tiles: &Tiles, p: &Point) -> usize {
-    DIRS
-        .iter()
-        .filter(|&d| {
-            let x = Point { x: p.x + d.x, y: p.y + d.y };
-            matches!(tiles.get(&x), Some(col) if *col == Color::Black)
-        })
-        .count()
+// Tiles packed as biased (q, r) in the high and low halves of a u64, so that
+// moving by a fixed offset is adding a fixed (wrapping) delta. Coordinates
+// must stay within +/-2^29, which leaves every key below 2^62.
+const BIAS: i64 = 1 << 29;
+const END: u64 = 1 << 63;
+
+fn pack(p: Axial) -> u64 {
+    (((p.q as i64 + BIAS) as u64) << 32) | (p.r as i64 + BIAS) as u64
 }
 
-fn get_points(tiles: &Tiles) -> Vec<Point> {
-    tiles
-        .keys()
-        .flat_map(|p| DIRS
-            .iter()
-            .map(|d| Point { x: p.x + d.x, y: p.y + d.y })
-            .collect_vec()
-        )
-        .unique()
-        .collect()
+fn unpack(k: u64) -> Axial {
+    Axial::new(((k >> 32) as i64 - BIAS) as isize, ((k & 0xffff_ffff) as i64 - BIAS) as isize)
 }
 
-fn day(tiles: &Tiles) -> Tiles {
-    get_points(&tiles)
-        .iter()
-        .filter(|&p| {
-            let n = count_black_neighbors(&tiles, p);
-            match tiles.get(p) {
-                Some(&Color::Black) => n == 1 || n == 2,
-                _ => n == 2,
+// Only black tiles are kept, sorted and followed by END. Shifting every tile
+// by the same offset keeps them sorted, so the neighbour counts come from
+// merging the list with its six shifted copies, without any hashing.
+#[derive(Debug, Clone)]
+pub struct HexLife {
+    black: Vec<u64>,
+    rule: Rule,
+    next: Vec<u64>,
+}
+
+impl HexLife {
+    pub fn new(black: impl IntoIterator<Item = Axial>, rule: Rule) -> HexLife {
+        assert!(!rule.next(false, 0), "A rule with B0 turns infinitely many tiles black");
+
+        let mut black: Vec<u64> = black.into_iter().map(pack).collect();
+        black.sort_unstable();
+        black.dedup();
+        black.push(END);
+        HexLife { black, rule, next: Vec::new() }
+    }
+
+    pub fn black(&self) -> impl Iterator<Item = Axial> + '_ {
+        self.black[..self.count()].iter().map(|&k| unpack(k))
+    }
+
+    pub fn is_black(&self, p: Axial) -> bool {
+        self.black.binary_search(&pack(p)).is_ok()
+    }
+
+    pub fn count(&self) -> usize {
+        self.black.len() - 1
+    }
+
+    pub fn step(&mut self) {
+        let mut deltas = [0u64; 7];
+        for (d, n) in deltas[1..].iter_mut().zip(Axial::ORIGIN.neighbours().iter()) {
+            *d = pack(Axial::ORIGIN).wrapping_sub(pack(*n));
+        }
+
+        let black = &self.black;
+        let mut cursors = [0; 7];
+        let mut heads = [0u64; 7];
+        for (h, &d) in heads.iter_mut().zip(deltas.iter()) {
+            *h = black[0].wrapping_add(d);
+        }
+
+        self.next.clear();
+        loop {
+            let p = *heads.iter().min().unwrap();
+            if p >= END >> 1 {
+                break;
             }
-        })
-        .map(|&p| (p, Color::Black))
+
+            // END is never matched, so the cursors stay in bounds.
+            let mut neighbours = 0;
+            let mut alive = false;
+            for (k, (i, h)) in cursors.iter_mut().zip(heads.iter_mut()).enumerate() {
+                if *h == p {
+                    *i += 1;
+                    *h = black[*i].wrapping_add(deltas[k]);
+                    if k == 0 {
+                        alive = true;
+                    } else {
+                        neighbours += 1;
+                    }
+                }
+            }
+
+            if self.rule.next(alive, neighbours) {
+                self.next.push(p);
+            }
+        }
+        self.next.push(END);
+
+        std::mem::swap(&mut self.black, &mut self.next);
+    }
+
+    pub fn run(&mut self, days: usize) {
+        for _ in 0..days {
+            self.step();
+        }
+    }
+
+    pub fn next(&self) -> HexLife {
+        let mut life = self.clone();
+        life.step();
+        life
+    }
+}
+
+pub fn floor_rule() -> Rule {
+    Rule::new(&[2], &[1, 2])
+}
+
+// Black tiles in doubled coordinates with north on top, in a box that fits
+// all days.
+pub fn frames(tiles: &[Tile], days: usize) -> Vec<Frame> {
+    let mut sim = Simulation::new(HexLife::new(flip_all(tiles), floor_rule()), HexLife::next);
+    let floors: Vec<HexLife> = sim.generations().take(days + 1).collect();
+
+    let all: Vec<Doubled> = floors.iter().flat_map(|t| t.black()).map(|p| p.to_doubled(Orientation::PointyTop)).collect();
+    let (x0, x1) = all.iter().fold((0, 0), |(a, b), p| (a.min(p.col), b.max(p.col)));
+    let (y0, y1) = all.iter().fold((0, 0), |(a, b), p| (a.min(p.row), b.max(p.row)));
+
+    floors
+        .iter()
+        .map(|t| (y0..=y1)
+            .map(|row| (x0..=x1)
+                .map(|col| match (Doubled { col, row }).to_axial(Orientation::PointyTop) {
+                    None => ' ',
+                    Some(p) if t.is_black(p) => '#',
+                    Some(_) => '.',
+                })
+                .collect())
+            .collect())
         .collect()
 }
 
@@ -134,30 +181,26 @@ fn day(tiles: &Tiles) -> Tiles {
 pub fn gen(input: &str) -> Vec<Tile> {
     input
         .lines()
-        .map(Tile::from)
+        .map(|line| line.parse().unwrap_or_else(|e| panic!("Parsing error: {}", e)))
         .collect()
 }
 
 #[aoc(day24, part1)]
 pub fn solve_part1(tiles: &Vec<Tile>) -> usize {
-    flip_all(tiles)
-        .values()
-        .filter(|&&c| c == Color::Black)
-        .count()
+    flip_all(tiles).len()
 }
 
 #[aoc(day24, part2)]
 pub fn solve_part2(tiles: &Vec<Tile>) -> usize {
-    let tiles = flip_all(tiles);
-
-    (0..100)
-        .fold(tiles, |tiles, _| day(&tiles))
-        .len()
+    let mut life = HexLife::new(flip_all(tiles), floor_rule());
+    life.run(100);
+    life.count()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::animation::frame_to_string;
 
     fn get_input() -> &'static str {
         return "sesenwnenenewseeswwswswwnenewsewsw
@@ -185,6 +228,9 @@ wseweeenwnesenwwwswnew";
     #[test]
     fn test_gen() {
         // println!("{:?}", gen(get_input()));
+        assert_eq!("nwwswee".parse::<Tile>().unwrap().identify(), Axial::ORIGIN);
+        assert_eq!("esenee".parse::<Tile>().unwrap().identify(), Axial::new(3, 0));
+        assert_eq!("enx".parse::<Tile>().unwrap_err().offset, 1);
     }
 
     #[test]
@@ -196,4 +242,37 @@ wseweeenwnesenwwwswnew";
     fn test_part2() {
         assert_eq!(solve_part2(&gen(get_input())), 2208);
     }
+
+    #[test]
+    fn test_frames() {
+        let frames = frames(&gen("esew\nnwwswee\nnwwswee"), 1);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frame_to_string(&frames[0]), ". \n #");
+        assert_eq!(frame_to_string(&frames[1]), ". \n .");
+    }
+
+    #[test]
+    fn test_days() {
+        let mut sim = Simulation::new(HexLife::new(flip_all(&gen(get_input())), floor_rule()), HexLife::next);
+        let counts: Vec<usize> = sim.generations().skip(1).take(10).map(|t| t.count()).collect();
+        assert_eq!(counts, vec![15, 12, 25, 14, 23, 28, 41, 37, 49, 37]);
+    }
+
+    #[test]
+    fn test_rules() {
+        let single = vec![Axial::ORIGIN];
+        let mut life = HexLife::new(single.clone(), "B2/S0".parse().unwrap());
+        life.step();
+        assert_eq!(life.black().collect::<Vec<_>>(), single);
+
+        let mut life = HexLife::new(single, "B1/S".parse().unwrap());
+        life.step();
+        assert_eq!(life.count(), 6);
+        life.step();
+        assert_eq!(life.count(), 6);
+
+        let mut life = HexLife::new(flip_all(&gen(get_input())), floor_rule());
+        life.run(200);
+        assert_eq!(life.count(), 8360);
+    }
 }
\ No newline at end of file
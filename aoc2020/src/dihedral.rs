@@ -0,0 +1,197 @@
+use std::fmt;
+
+// Symmetry of a rectangle: an optional upside down flip followed by `rot`
+// quarter turns counterclockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct D4 {
+    flip: bool,
+    rot: u8,
+}
+
+impl D4 {
+    pub const IDENTITY: D4 = D4 { flip: false, rot: 0 };
+
+    pub fn rotation(quarter_turns: usize) -> D4 {
+        D4 { flip: false, rot: (quarter_turns % 4) as u8 }
+    }
+
+    pub fn reflection() -> D4 {
+        D4 { flip: true, rot: 0 }
+    }
+
+    // Rotations first, then the flipped ones, `D4::all()[t.index()] == t`.
+    pub fn all() -> [D4; 8] {
+        let mut ret = [D4::IDENTITY; 8];
+        for (i, t) in ret.iter_mut().enumerate() {
+            *t = D4 { flip: i >= 4, rot: (i % 4) as u8 };
+        }
+        ret
+    }
+
+    pub fn index(self) -> usize {
+        self.flip as usize * 4 + self.rot as usize
+    }
+
+    pub fn is_flipped(self) -> bool {
+        self.flip
+    }
+
+    pub fn quarter_turns(self) -> usize {
+        self.rot as usize
+    }
+
+    // `self` applied first, then `other`.
+    pub fn then(self, other: D4) -> D4 {
+        let rot = if other.flip {
+            (4 + other.rot - self.rot) % 4
+        } else {
+            (other.rot + self.rot) % 4
+        };
+
+        D4 { flip: self.flip != other.flip, rot }
+    }
+
+    pub fn inverse(self) -> D4 {
+        if self.flip {
+            self
+        } else {
+            D4::rotation(4 - self.rot as usize)
+        }
+    }
+
+    // Size of a `width` x `height` grid once transformed.
+    pub fn dims(self, width: usize, height: usize) -> (usize, usize) {
+        if self.rot & 1 == 0 {
+            (width, height)
+        } else {
+            (height, width)
+        }
+    }
+
+    // Where cell (y, x) of a `width` x `height` grid ends up.
+    pub fn apply(self, width: usize, height: usize, y: usize, x: usize) -> (usize, usize) {
+        let (mut w, mut h, mut y, mut x) = (width, height, y, x);
+
+        if self.flip {
+            y = h - 1 - y;
+        }
+        for _ in 0..self.rot {
+            let (ny, nx) = (w - 1 - x, y);
+            y = ny;
+            x = nx;
+            std::mem::swap(&mut w, &mut h);
+        }
+
+        (y, x)
+    }
+
+    pub fn view<T>(self, grid: &[Vec<T>]) -> View<'_, T> {
+        let (width, height) = (grid.first().map_or(0, |l| l.len()), grid.len());
+        let (w, h) = self.dims(width, height);
+
+        View {
+            grid,
+            inverse: self.inverse(),
+            width: w,
+            height: h,
+        }
+    }
+}
+
+impl fmt::Display for D4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}r{}", if self.flip { "f" } else { "" }, self.rot as usize * 90)
+    }
+}
+
+// A transformed grid, read through the index mapping without copying.
+pub struct View<'a, T> {
+    grid: &'a [Vec<T>],
+    inverse: D4,
+    width: usize,
+    height: usize,
+}
+
+impl<'a, T> View<'a, T> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, y: usize, x: usize) -> &'a T {
+        let (y, x) = self.inverse.apply(self.width, self.height, y, x);
+        &self.grid[y][x]
+    }
+
+    pub fn row(&self, y: usize) -> impl Iterator<Item = &'a T> + '_ {
+        (0..self.width).map(move |x| self.get(y, x))
+    }
+
+    pub fn col(&self, x: usize) -> impl Iterator<Item = &'a T> + '_ {
+        (0..self.height).map(move |y| self.get(y, x))
+    }
+
+    pub fn to_vec(&self) -> Vec<Vec<T>> where T: Clone {
+        (0..self.height)
+            .map(|y| self.row(y).cloned().collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> Vec<Vec<char>> {
+        vec!["abc".chars().collect(), "def".chars().collect()]
+    }
+
+    fn lines(v: Vec<Vec<char>>) -> Vec<String> {
+        v.into_iter().map(|l| l.into_iter().collect()).collect()
+    }
+
+    #[test]
+    fn test_view() {
+        let g = grid();
+        assert_eq!(lines(D4::IDENTITY.view(&g).to_vec()), vec!["abc", "def"]);
+        assert_eq!(lines(D4::rotation(1).view(&g).to_vec()), vec!["cf", "be", "ad"]);
+        assert_eq!(lines(D4::rotation(2).view(&g).to_vec()), vec!["fed", "cba"]);
+        assert_eq!(lines(D4::reflection().view(&g).to_vec()), vec!["def", "abc"]);
+        assert_eq!(lines(D4::all()[5].view(&g).to_vec()), vec!["fc", "eb", "da"]);
+
+        let v = D4::rotation(3).view(&g);
+        assert_eq!((v.width(), v.height()), (2, 3));
+        assert_eq!(v.col(0).collect::<String>(), "def");
+    }
+
+    #[test]
+    fn test_group() {
+        let g = grid();
+
+        for (i, &a) in D4::all().iter().enumerate() {
+            assert_eq!(a.index(), i);
+            assert_eq!(a.then(a.inverse()), D4::IDENTITY);
+            assert_eq!(a.inverse().then(a), D4::IDENTITY);
+
+            for &b in D4::all().iter() {
+                let once = a.then(b).view(&g).to_vec();
+                let twice = b.view(&a.view(&g).to_vec()).to_vec();
+                assert_eq!(once, twice, "{} then {}", a, b);
+            }
+        }
+
+        let w = 5;
+        let h = 3;
+        for &t in D4::all().iter() {
+            let (tw, th) = t.dims(w, h);
+            let (y, x) = t.apply(w, h, 1, 4);
+            assert_eq!(t.inverse().apply(tw, th, y, x), (1, 4));
+        }
+
+        assert_eq!(D4::all().iter().map(|t| t.to_string()).collect::<Vec<_>>(),
+                   vec!["r0", "r90", "r180", "r270", "fr0", "fr90", "fr180", "fr270"]);
+    }
+}
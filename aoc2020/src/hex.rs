@@ -0,0 +1,367 @@
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::combinator::value;
+use nom::multi::many0;
+use nom::IResult;
+
+// Axial coordinates, `r` grows downwards (south on pointy-top grids).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Axial {
+    pub q: isize,
+    pub r: isize,
+}
+
+// Cube coordinates, always q + r + s == 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Cube {
+    pub q: isize,
+    pub r: isize,
+    pub s: isize,
+}
+
+// Doubled-width coordinates on pointy-top grids and doubled-height ones on
+// flat-top grids, `col + row` is always even.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Doubled {
+    pub col: isize,
+    pub row: isize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    PointyTop,
+    FlatTop,
+}
+
+// Counterclockwise from east.
+const DIRECTIONS: [Axial; 6] = [
+    Axial { q: 1, r: 0 },
+    Axial { q: 1, r: -1 },
+    Axial { q: 0, r: -1 },
+    Axial { q: -1, r: 0 },
+    Axial { q: -1, r: 1 },
+    Axial { q: 0, r: 1 },
+];
+
+const POINTY_NAMES: [&str; 6] = ["e", "ne", "nw", "w", "sw", "se"];
+const FLAT_NAMES: [&str; 6] = ["se", "ne", "n", "nw", "sw", "s"];
+
+impl Orientation {
+    // Neighbour offsets counterclockwise, starting with the first one at or
+    // above east.
+    pub fn directions(self) -> [(&'static str, Axial); 6] {
+        let (names, skip) = match self {
+            Orientation::PointyTop => (POINTY_NAMES, 0),
+            Orientation::FlatTop => (FLAT_NAMES, 1),
+        };
+
+        let mut ret = [("", Axial::ORIGIN); 6];
+        for (i, r) in ret.iter_mut().enumerate() {
+            let j = (i + skip) % 6;
+            *r = (names[j], DIRECTIONS[j]);
+        }
+        ret
+    }
+
+    pub fn direction(self, name: &str) -> Option<Axial> {
+        self.directions().iter().find(|(n, _)| *n == name).map(|&(_, d)| d)
+    }
+}
+
+impl Axial {
+    pub const ORIGIN: Axial = Axial { q: 0, r: 0 };
+
+    pub fn new(q: isize, r: isize) -> Axial {
+        Axial { q, r }
+    }
+
+    pub fn to_cube(self) -> Cube {
+        Cube { q: self.q, r: self.r, s: -self.q - self.r }
+    }
+
+    pub fn to_doubled(self, orientation: Orientation) -> Doubled {
+        match orientation {
+            Orientation::PointyTop => Doubled { col: 2 * self.q + self.r, row: self.r },
+            Orientation::FlatTop => Doubled { col: self.q, row: 2 * self.r + self.q },
+        }
+    }
+
+    pub fn length(self) -> usize {
+        let c = self.to_cube();
+        c.q.unsigned_abs().max(c.r.unsigned_abs()).max(c.s.unsigned_abs())
+    }
+
+    pub fn distance(self, other: Axial) -> usize {
+        (self - other).length()
+    }
+
+    pub fn neighbours(self) -> [Axial; 6] {
+        let mut ret = DIRECTIONS;
+        for d in ret.iter_mut() {
+            *d = *d + self;
+        }
+        ret
+    }
+
+    // Hexes at exactly `radius` steps, counterclockwise from the south-west
+    // corner.
+    pub fn ring(self, radius: usize) -> Vec<Axial> {
+        if radius == 0 {
+            return vec![self];
+        }
+
+        let mut ret = Vec::with_capacity(6 * radius);
+        let mut hex = self + DIRECTIONS[4] * radius as isize;
+        for &d in DIRECTIONS.iter() {
+            for _ in 0..radius {
+                ret.push(hex);
+                hex = hex + d;
+            }
+        }
+        ret
+    }
+
+    // Rings of growing radius, so every hex within `radius` steps.
+    pub fn spiral(self, radius: usize) -> Vec<Axial> {
+        (0..=radius).flat_map(|k| self.ring(k)).collect()
+    }
+
+    // Hexes crossed by the straight segment between the centres, both ends
+    // included.
+    pub fn line_to(self, other: Axial) -> Vec<Axial> {
+        let n = self.distance(other);
+        if n == 0 {
+            return vec![self];
+        }
+
+        // Nudged so that points on an edge always fall on the same side.
+        let (a, b) = (self.to_cube(), other.to_cube());
+        let lerp = |x: isize, y: isize, t: f64, eps: f64| x as f64 + eps + (y - x) as f64 * t;
+
+        (0..=n)
+            .map(|i| {
+                let t = i as f64 / n as f64;
+                Cube::round(lerp(a.q, b.q, t, 1e-6), lerp(a.r, b.r, t, 2e-6), lerp(a.s, b.s, t, -3e-6)).to_axial()
+            })
+            .collect()
+    }
+}
+
+impl Cube {
+    pub fn to_axial(self) -> Axial {
+        Axial { q: self.q, r: self.r }
+    }
+
+    // Nearest hex, the coordinate that moved most is recomputed from the
+    // other two.
+    pub fn round(q: f64, r: f64, s: f64) -> Cube {
+        let (mut rq, mut rr, mut rs) = (q.round(), r.round(), s.round());
+        let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+
+        if dq > dr && dq > ds {
+            rq = -rr - rs;
+        } else if dr > ds {
+            rr = -rq - rs;
+        } else {
+            rs = -rq - rr;
+        }
+
+        Cube { q: rq as isize, r: rr as isize, s: rs as isize }
+    }
+
+    pub fn distance(self, other: Cube) -> usize {
+        self.to_axial().distance(other.to_axial())
+    }
+}
+
+impl From<Cube> for Axial {
+    fn from(c: Cube) -> Axial {
+        c.to_axial()
+    }
+}
+
+impl From<Axial> for Cube {
+    fn from(a: Axial) -> Cube {
+        a.to_cube()
+    }
+}
+
+impl Doubled {
+    // None when `col + row` is odd, there is no hex there.
+    pub fn to_axial(self, orientation: Orientation) -> Option<Axial> {
+        if (self.col + self.row) & 1 != 0 {
+            return None;
+        }
+
+        Some(match orientation {
+            Orientation::PointyTop => Axial { q: (self.col - self.row) / 2, r: self.row },
+            Orientation::FlatTop => Axial { q: self.col, r: (self.row - self.col) / 2 },
+        })
+    }
+}
+
+impl Add for Axial {
+    type Output = Axial;
+
+    fn add(self, other: Axial) -> Axial {
+        Axial { q: self.q + other.q, r: self.r + other.r }
+    }
+}
+
+impl Sub for Axial {
+    type Output = Axial;
+
+    fn sub(self, other: Axial) -> Axial {
+        Axial { q: self.q - other.q, r: self.r - other.r }
+    }
+}
+
+impl Neg for Axial {
+    type Output = Axial;
+
+    fn neg(self) -> Axial {
+        Axial { q: -self.q, r: -self.r }
+    }
+}
+
+impl Mul<isize> for Axial {
+    type Output = Axial;
+
+    fn mul(self, k: isize) -> Axial {
+        Axial { q: self.q * k, r: self.r * k }
+    }
+}
+
+impl fmt::Display for Axial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.q, self.r)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathError {
+    pub offset: usize,
+    pub found: String,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid direction at offset {}: {:?}", self.offset, self.found)
+    }
+}
+
+// Two letter names first so that "se" is not read as "s" then "e".
+fn pointy_step(s: &str) -> IResult<&str, Axial> {
+    alt((
+        value(DIRECTIONS[1], tag("ne")),
+        value(DIRECTIONS[2], tag("nw")),
+        value(DIRECTIONS[4], tag("sw")),
+        value(DIRECTIONS[5], tag("se")),
+        value(DIRECTIONS[0], tag("e")),
+        value(DIRECTIONS[3], tag("w")),
+    ))(s)
+}
+
+fn flat_step(s: &str) -> IResult<&str, Axial> {
+    alt((
+        value(DIRECTIONS[1], tag("ne")),
+        value(DIRECTIONS[3], tag("nw")),
+        value(DIRECTIONS[4], tag("sw")),
+        value(DIRECTIONS[0], tag("se")),
+        value(DIRECTIONS[2], tag("n")),
+        value(DIRECTIONS[5], tag("s")),
+    ))(s)
+}
+
+// Steps written without separators, like "nwwswee".
+pub fn parse_path(s: &str, orientation: Orientation) -> Result<Vec<Axial>, PathError> {
+    let step = match orientation {
+        Orientation::PointyTop => pointy_step,
+        Orientation::FlatTop => flat_step,
+    };
+
+    let (rest, steps) = many0(step)(s).map_err(|_| PathError { offset: 0, found: s.to_string() })?;
+    if !rest.is_empty() {
+        return Err(PathError {
+            offset: s.len() - rest.len(),
+            found: rest.chars().take(2).collect(),
+        });
+    }
+
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coordinates() {
+        let a = Axial::new(2, -3);
+        assert_eq!(a.to_cube(), Cube { q: 2, r: -3, s: 1 });
+        assert_eq!(Axial::from(Cube::from(a)), a);
+
+        for &o in [Orientation::PointyTop, Orientation::FlatTop].iter() {
+            for &h in Axial::ORIGIN.spiral(3).iter() {
+                assert_eq!(h.to_doubled(o).to_axial(o), Some(h));
+            }
+        }
+        assert_eq!(a.to_doubled(Orientation::PointyTop), Doubled { col: 1, row: -3 });
+        assert_eq!(a.to_doubled(Orientation::FlatTop), Doubled { col: 2, row: -4 });
+        assert_eq!(Doubled { col: 1, row: 0 }.to_axial(Orientation::PointyTop), None);
+
+        assert_eq!(a.length(), 3);
+        assert_eq!(a.distance(Axial::new(-1, 0)), 3);
+        assert_eq!(a.to_cube().distance(Cube::default()), 3);
+    }
+
+    #[test]
+    fn test_shapes() {
+        let o = Axial::new(1, 1);
+        assert_eq!(o.ring(0), vec![o]);
+        let ring = o.ring(2);
+        assert_eq!(ring.len(), 12);
+        assert!(ring.iter().all(|h| h.distance(o) == 2));
+        assert_eq!(ring[0], Axial::new(-1, 3));
+        assert_eq!(o.spiral(2).len(), 19);
+
+        assert!(o.neighbours().iter().all(|n| n.distance(o) == 1));
+
+        assert_eq!(Axial::ORIGIN.line_to(Axial::new(3, 0)),
+                   vec![Axial::new(0, 0), Axial::new(1, 0), Axial::new(2, 0), Axial::new(3, 0)]);
+        let line = Axial::ORIGIN.line_to(Axial::new(2, -4));
+        assert_eq!(line.len(), 5);
+        assert!(line.windows(2).all(|w| w[0].distance(w[1]) == 1));
+        assert_eq!(Axial::new(4, 4).line_to(Axial::new(4, 4)), vec![Axial::new(4, 4)]);
+    }
+
+    #[test]
+    fn test_directions() {
+        let pointy = Orientation::PointyTop.directions();
+        assert_eq!(pointy[0], ("e", Axial::new(1, 0)));
+        let flat = Orientation::FlatTop.directions();
+        assert_eq!(flat[0], ("ne", Axial::new(1, -1)));
+        assert_eq!(flat[5], ("se", Axial::new(1, 0)));
+        assert_eq!(Orientation::FlatTop.direction("n"), Some(Axial::new(0, -1)));
+        assert_eq!(Orientation::PointyTop.direction("n"), None);
+    }
+
+    #[test]
+    fn test_parse_path() {
+        let path = parse_path("nwwswee", Orientation::PointyTop).unwrap();
+        assert_eq!(path.len(), 5);
+        assert_eq!(path.iter().fold(Axial::ORIGIN, |a, &b| a + b), Axial::ORIGIN);
+
+        let path = parse_path("nsse", Orientation::FlatTop).unwrap();
+        assert_eq!(path, vec![Axial::new(0, -1), Axial::new(0, 1), Axial::new(1, 0)]);
+
+        assert_eq!(parse_path("", Orientation::PointyTop), Ok(vec![]));
+        assert_eq!(parse_path("eenx", Orientation::PointyTop),
+                   Err(PathError { offset: 2, found: "nx".to_string() }));
+        assert_eq!(parse_path("sen", Orientation::PointyTop).unwrap_err().to_string(),
+                   "invalid direction at offset 2: \"n\"");
+    }
+}
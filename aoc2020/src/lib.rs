@@ -3,6 +3,15 @@ extern crate  aoc_runner;
 extern crate  aoc_runner_derive;
 extern crate nom;
 
+pub mod animation;
+pub mod assignment;
+pub mod bignum;
+pub mod cellular;
+pub mod dihedral;
+pub mod hex;
+pub mod raster;
+pub mod simulation;
+
 pub mod day1;
 pub mod day2;
 pub mod day3;
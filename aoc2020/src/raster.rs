@@ -0,0 +1,302 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+pub type Rgb = [u8; 3];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &x| {
+        let a = (a + x as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+// zlib stream made of stored (uncompressed) deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut chunks = data.chunks(0xffff).peekable();
+
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let len = chunk.len() as u16;
+        out.push(if chunks.peek().is_none() { 1 } else { 0 });
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Variable width LSB-first LZW as used by GIF, codes are at most 12 bits.
+fn lzw(min_size: u8, data: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << min_size;
+    let mut out = Vec::new();
+    let mut bits = 0u32;
+    let mut nbits = 0;
+    let mut width = min_size + 1;
+    let mut next = clear + 2;
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+
+    let mut emit = |code: u16, width: u8, out: &mut Vec<u8>| {
+        bits |= (code as u32) << nbits;
+        nbits += width;
+        while nbits >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            nbits -= 8;
+        }
+    };
+
+    emit(clear, width, &mut out);
+    let mut cur: Option<u16> = None;
+    for &k in data {
+        let prefix = match cur {
+            None => {
+                cur = Some(k as u16);
+                continue;
+            }
+            Some(prefix) => prefix,
+        };
+        if let Some(&code) = table.get(&(prefix, k)) {
+            cur = Some(code);
+            continue;
+        }
+
+        emit(prefix, width, &mut out);
+        cur = Some(k as u16);
+        if next == 4095 {
+            emit(clear, width, &mut out);
+            table.clear();
+            width = min_size + 1;
+            next = clear + 2;
+        } else {
+            table.insert((prefix, k), next);
+            if next == 1 << width {
+                width += 1;
+            }
+            next += 1;
+        }
+    }
+    if let Some(code) = cur {
+        emit(code, width, &mut out);
+        if next == 1 << width && width < 12 {
+            width += 1;
+        }
+    }
+    emit(clear + 1, width, &mut out);
+    emit(0, 7, &mut out);
+
+    out
+}
+
+impl Raster {
+    pub fn new(width: usize, height: usize, fill: Rgb) -> Raster {
+        Raster {
+            width,
+            height,
+            pixels: vec![fill; width * height],
+        }
+    }
+
+    // Each grid cell becomes a `scale` x `scale` square.
+    pub fn from_grid<T>(grid: &[Vec<T>], scale: usize, colour: impl Fn(&T) -> Rgb) -> Raster {
+        let height = grid.len() * scale;
+        let width = grid.iter().map(|l| l.len()).max().unwrap_or(0) * scale;
+        let mut raster = Raster::new(width, height, [0, 0, 0]);
+
+        for (y, line) in grid.iter().enumerate() {
+            for (x, cell) in line.iter().enumerate() {
+                let c = colour(cell);
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        raster.set(x * scale + dx, y * scale + dy, c);
+                    }
+                }
+            }
+        }
+
+        raster
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, c: Rgb) {
+        self.pixels[y * self.width + x] = c;
+    }
+
+    // Binary PPM (P6).
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend(self.pixels.iter().flatten());
+        out
+    }
+
+    // 8-bit RGB PNG, not compressed but readable by any viewer.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut out = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        png_chunk(&mut out, b"IHDR", &header);
+
+        let mut raw = Vec::with_capacity(self.height * (self.width * 3 + 1));
+        for line in self.pixels.chunks(self.width.max(1)).take(self.height) {
+            raw.push(0);
+            raw.extend(line.iter().flatten());
+        }
+        png_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut out, b"IEND", &[]);
+
+        out
+    }
+}
+
+// Animated GIF looping forever, `delay` in hundredths of a second. All frames
+// must have the size of the first one and use at most 256 colours together.
+pub fn to_gif(frames: &[Raster], delay: u16) -> Option<Vec<u8>> {
+    let first = frames.first()?;
+    if frames.iter().any(|f| (f.width, f.height) != (first.width, first.height)) {
+        return None;
+    }
+
+    let mut palette: Vec<Rgb> = Vec::new();
+    let mut index: HashMap<Rgb, u8> = HashMap::new();
+    for &c in frames.iter().flat_map(|f| f.pixels.iter()) {
+        if let Entry::Vacant(e) = index.entry(c) {
+            if palette.len() == 256 {
+                return None;
+            }
+            e.insert(palette.len() as u8);
+            palette.push(c);
+        }
+    }
+
+    let mut depth = 1;
+    while 1 << depth < palette.len() {
+        depth += 1;
+    }
+    palette.resize(1 << depth, [0, 0, 0]);
+
+    let mut out = b"GIF89a".to_vec();
+    out.extend_from_slice(&(first.width as u16).to_le_bytes());
+    out.extend_from_slice(&(first.height as u16).to_le_bytes());
+    out.extend_from_slice(&[0x80 | ((depth - 1) << 4) | (depth - 1), 0, 0]);
+    out.extend(palette.iter().flatten());
+    out.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+    let min_size = depth.max(2);
+    for f in frames {
+        out.extend_from_slice(&[0x21, 0xf9, 0x04, 0x00]);
+        out.extend_from_slice(&delay.to_le_bytes());
+        out.extend_from_slice(&[0x00, 0x00, 0x2c, 0, 0, 0, 0]);
+        out.extend_from_slice(&(f.width as u16).to_le_bytes());
+        out.extend_from_slice(&(f.height as u16).to_le_bytes());
+        out.extend_from_slice(&[0x00, min_size]);
+
+        let data: Vec<u8> = f.pixels.iter().map(|c| index[c]).collect();
+        for block in lzw(min_size, &data).chunks(255) {
+            out.push(block.len() as u8);
+            out.extend_from_slice(block);
+        }
+        out.push(0);
+    }
+    out.push(0x3b);
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_ppm() {
+        let grid = vec![vec![true, false], vec![false, true]];
+        let raster = Raster::from_grid(&grid, 2, |&b| if b { [255, 0, 0] } else { [0, 0, 255] });
+        assert_eq!((raster.width(), raster.height()), (4, 4));
+        assert_eq!(raster.get(1, 1), [255, 0, 0]);
+        assert_eq!(raster.get(2, 1), [0, 0, 255]);
+
+        let ppm = raster.to_ppm();
+        assert!(ppm.starts_with(b"P6\n4 4\n255\n"));
+        assert_eq!(ppm.len(), 11 + 4 * 4 * 3);
+    }
+
+    #[test]
+    fn test_png() {
+        let png = Raster::new(3, 2, [1, 2, 3]).to_png();
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 3, 0, 0, 0, 2]);
+        assert_eq!(&png[png.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+
+        let idat = &png[33 + 8..];
+        let zlib = &idat[..idat.len() - 12 - 4];
+        let raw = [0, 1, 2, 3, 1, 2, 3, 1, 2, 3];
+        assert_eq!(&zlib[7..7 + 2 * raw.len()], &[&raw[..], &raw[..]].concat()[..]);
+    }
+    #[test]
+    fn test_gif() {
+        assert_eq!(lzw(2, &[1, 1, 1, 1, 1, 2, 2, 2, 2, 2]), vec![0x8c, 0x2d, 0x99, 0x05]);
+
+        let frames = vec![Raster::new(2, 2, [0, 0, 0]), Raster::new(2, 2, [255, 255, 255])];
+        let gif = to_gif(&frames, 10).unwrap();
+        assert!(gif.starts_with(b"GIF89a\x02\x00\x02\x00\x80"));
+        assert_eq!(&gif[13..19], &[0, 0, 0, 255, 255, 255]);
+        assert_eq!(gif.last(), Some(&0x3b));
+
+        assert!(to_gif(&[Raster::new(1, 1, [0, 0, 0]), Raster::new(2, 1, [0, 0, 0])], 10).is_none());
+    }
+}
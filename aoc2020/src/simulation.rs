@@ -0,0 +1,184 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// The states from generation `start` on repeat every `period` generations, a
+// period of 1 is a fixed point.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cycle {
+    pub start: usize,
+    pub period: usize,
+}
+
+impl Cycle {
+    pub fn is_fixed_point(&self) -> bool {
+        self.period == 1
+    }
+
+    // Generation that holds the same state as generation `n`.
+    pub fn reduce(&self, n: usize) -> usize {
+        if n < self.start {
+            n
+        } else {
+            self.start + (n - self.start) % self.period
+        }
+    }
+}
+
+pub struct Simulation<S, F> {
+    state: S,
+    step: F,
+    generation: usize,
+}
+
+fn hash<S: Hash>(s: &S) -> u64 {
+    let mut h = DefaultHasher::new();
+    s.hash(&mut h);
+    h.finish()
+}
+
+// Hashes are compared first so that most comparisons are cheap.
+struct Hashed<S> {
+    hash: u64,
+    state: S,
+}
+
+impl<S: Hash + Eq> Hashed<S> {
+    fn new(state: S) -> Hashed<S> {
+        Hashed { hash: hash(&state), state }
+    }
+
+    fn same(&self, other: &Hashed<S>) -> bool {
+        self.hash == other.hash && self.state == other.state
+    }
+}
+
+impl<S: Clone, F: FnMut(&S) -> S> Simulation<S, F> {
+    pub fn new(state: S, step: F) -> Simulation<S, F> {
+        Simulation { state, step, generation: 0 }
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn into_state(self) -> S {
+        self.state
+    }
+
+    pub fn step(&mut self) -> &S {
+        self.state = (self.step)(&self.state);
+        self.generation += 1;
+        &self.state
+    }
+
+    pub fn run(&mut self, steps: usize) -> &S {
+        for _ in 0..steps {
+            self.step();
+        }
+        &self.state
+    }
+
+    // The current state first, then one state per step.
+    pub fn generations(&mut self) -> Generations<'_, S, F> {
+        Generations { sim: self, started: false }
+    }
+
+    // Brent's algorithm, giving up after `limit` steps. When a cycle is found
+    // the simulation is left on the generation where it begins.
+    pub fn find_cycle(&mut self, limit: Option<usize>) -> Option<Cycle> where S: Hash + Eq {
+        let advance = |s: &Hashed<S>, step: &mut F| Hashed::new(step(&s.state));
+
+        let mut steps = 1;
+        let mut power = 1;
+        let mut period = 1;
+        let mut tortoise = Hashed::new(self.state.clone());
+        let mut hare = advance(&tortoise, &mut self.step);
+
+        while !tortoise.same(&hare) {
+            if limit.is_some() && limit < Some(steps) {
+                return None;
+            }
+            steps += 1;
+            if power == period {
+                tortoise = Hashed { hash: hare.hash, state: hare.state.clone() };
+                power *= 2;
+                period = 0;
+            }
+            hare = advance(&hare, &mut self.step);
+            period += 1;
+        }
+
+        let mut tortoise = Hashed::new(self.state.clone());
+        let mut hare = Hashed::new(self.state.clone());
+        for _ in 0..period {
+            hare = advance(&hare, &mut self.step);
+        }
+
+        let mut start = 0;
+        while !tortoise.same(&hare) {
+            tortoise = advance(&tortoise, &mut self.step);
+            hare = advance(&hare, &mut self.step);
+            start += 1;
+        }
+
+        self.state = tortoise.state;
+        self.generation += start;
+        Some(Cycle { start: self.generation, period })
+    }
+}
+
+pub struct Generations<'a, S, F> {
+    sim: &'a mut Simulation<S, F>,
+    started: bool,
+}
+
+impl<'a, S: Clone, F: FnMut(&S) -> S> Iterator for Generations<'a, S, F> {
+    type Item = S;
+
+    fn next(&mut self) -> Option<S> {
+        if self.started {
+            self.sim.step();
+        }
+        self.started = true;
+        Some(self.sim.state.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle() {
+        // 3 -> 10 -> 5 -> 16 -> 8 -> 4 -> 2 -> 1 -> 4
+        let collatz = |&n: &u64| if n & 1 == 0 { n / 2 } else { 3 * n + 1 };
+
+        let mut sim = Simulation::new(3, collatz);
+        let cycle = sim.find_cycle(None).unwrap();
+        assert_eq!(cycle, Cycle { start: 5, period: 3 });
+        assert_eq!((sim.generation(), *sim.state()), (5, 4));
+        assert_eq!(cycle.reduce(4), 4);
+        assert_eq!(cycle.reduce(11), 5);
+
+        let mut sim = Simulation::new(7, |&n: &u64| if n > 1 { n - 1 } else { n });
+        let cycle = sim.find_cycle(None).unwrap();
+        assert!(cycle.is_fixed_point());
+        assert_eq!(cycle.start, 6);
+
+        let mut sim = Simulation::new(0, |&n: &u64| n + 1);
+        assert_eq!(sim.find_cycle(Some(100)), None);
+        assert_eq!(sim.generation(), 0);
+    }
+
+    #[test]
+    fn test_generations() {
+        let mut sim = Simulation::new(1, |&n: &u64| n * 2);
+        assert_eq!(sim.generations().take(5).collect::<Vec<_>>(), vec![1, 2, 4, 8, 16]);
+        assert_eq!(sim.generation(), 4);
+        assert_eq!(*sim.run(2), 64);
+    }
+}